@@ -0,0 +1,214 @@
+// Tonal adjustments: brightness, contrast, gamma and exposure
+// Every adjustment is baked into a 256-entry lookup table, and a chain of adjustments composes into
+// one table, so any number of them costs a single pass over the image
+
+use std::io;
+
+use crate::color;
+use crate::image::Image;
+use crate::pixel::{self, ChannelMode, Pixel};
+use crate::Data;
+
+// A tonal adjustment on normalized values
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Adjustment {
+    // Added to every value, -1 to 1
+    Brightness(f32),
+    // Distances from the pivot are multiplied by the amount, 1 leaves the image as it is
+    Contrast {amount: f32, pivot: f32},
+    // Values are raised to 1 / gamma, so gammas above 1 brighten the midtones
+    Gamma(f32),
+    // Light is multiplied by 2 to the power of the stops, in linear light like a camera exposure
+    Exposure(f32),
+}
+
+impl Adjustment {
+    // Contrast around middle gray
+    pub fn contrast(amount: f32) -> Adjustment {
+        Adjustment::Contrast {amount, pivot: 0.5}
+    }
+
+    // Adjustment written as name=value: brightness=0.1, contrast=1.2 (or contrast=1.2@0.4 for a
+    // pivot of 0.4), gamma=2.2 or exposure=-1
+    pub fn parse(text: &str) -> Result<Adjustment, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid adjustment: {}", text));
+        let number = |value: &str| value.trim().parse::<f32>().map_err(|_| invalid());
+        let (name, value) = text.split_once('=').ok_or_else(invalid)?;
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "brightness" => Ok(Adjustment::Brightness(number(value)?)),
+            "contrast" => match value.split_once('@') {
+                Some((amount, pivot)) => Ok(Adjustment::Contrast {amount: number(amount)?, pivot: number(pivot)?}),
+                None => Ok(Adjustment::contrast(number(value)?)),
+            },
+            "gamma" => match number(value)? {
+                gamma if gamma > 0.0 => Ok(Adjustment::Gamma(gamma)),
+                _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Gamma must be greater than zero")),
+            },
+            "exposure" => Ok(Adjustment::Exposure(number(value)?)),
+            _ => Err(invalid()),
+        }
+    }
+
+    // Adjusts one normalized value
+    pub fn apply(self, value: f32) -> f32 {
+        match self {
+            Adjustment::Brightness(amount) => value + amount,
+            Adjustment::Contrast {amount, pivot} => (value - pivot) * amount + pivot,
+            Adjustment::Gamma(gamma) => value.max(0.0).powf(1.0 / gamma),
+            Adjustment::Exposure(stops) => color::linear_to_srgb(color::srgb_to_linear(value) * 2f32.powf(stops)),
+        }
+    }
+}
+
+// Table of output values for the 256 evenly spaced inputs from 0 to 1, outputs kept within 0 to 1
+#[derive(Clone, PartialEq, Debug)]
+pub struct Lut {
+    pub values: [f32; 256],
+}
+
+impl Lut {
+    // Leaves every value as it is
+    pub fn identity() -> Lut {
+        Lut::from_fn(|value| value)
+    }
+
+    // Table sampled from a function of normalized values
+    pub fn from_fn(f: impl Fn(f32) -> f32) -> Lut {
+        Lut {values: std::array::from_fn(|i| f(i as f32 / 255.0).clamp(0.0, 1.0))}
+    }
+
+    // Table of one adjustment
+    pub fn from_adjustment(adjustment: Adjustment) -> Lut {
+        Lut::from_fn(|value| adjustment.apply(value))
+    }
+
+    // One table doing every adjustment in turn
+    pub fn from_adjustments(adjustments: &[Adjustment]) -> Lut {
+        adjustments.iter().fold(Lut::identity(), |lut, adjustment| lut.then(&Lut::from_adjustment(*adjustment)))
+    }
+
+    // Output for a normalized value, interpolating between entries for values that fall between them
+    // 8-bit values land exactly on an entry
+    pub fn lookup(&self, value: f32) -> f32 {
+        let position = value.clamp(0.0, 1.0) * 255.0;
+        let index = (position as usize).min(254);
+        let fraction = position - index as f32;
+        self.values[index] + (self.values[index + 1] - self.values[index]) * fraction
+    }
+
+    // Table doing this one and then another
+    pub fn then(&self, next: &Lut) -> Lut {
+        Lut {values: self.values.map(|value| next.lookup(value))}
+    }
+
+    // Looks up every color channel (or the luminance only) of normalized BGRA values, keeping alpha
+    pub fn apply(&self, mut bgra: [f32; 4], mode: ChannelMode) -> [f32; 4] {
+        match mode {
+            ChannelMode::PerChannel => {
+                for channel in bgra.iter_mut().take(3) {
+                    *channel = self.lookup(*channel);
+                }
+            }
+            ChannelMode::Luminance => bgra = pixel::with_luma(bgra, self.lookup(pixel::luma(bgra[0], bgra[1], bgra[2]))),
+        }
+        bgra
+    }
+}
+
+// Separate tables for the red, green and blue channels
+#[derive(Clone, PartialEq, Debug)]
+pub struct ChannelLuts {
+    pub red: Lut,
+    pub green: Lut,
+    pub blue: Lut,
+}
+
+impl ChannelLuts {
+    // The same table for every channel
+    pub fn uniform(lut: &Lut) -> ChannelLuts {
+        ChannelLuts {red: lut.clone(), green: lut.clone(), blue: lut.clone()}
+    }
+
+    // Tables doing these and then others, channel by channel
+    pub fn then(&self, next: &ChannelLuts) -> ChannelLuts {
+        ChannelLuts {red: self.red.then(&next.red), green: self.green.then(&next.green), blue: self.blue.then(&next.blue)}
+    }
+
+    // Looks up each color channel of normalized BGRA values in its own table, keeping alpha
+    pub fn apply(&self, bgra: [f32; 4]) -> [f32; 4] {
+        [self.blue.lookup(bgra[0]), self.green.lookup(bgra[1]), self.red.lookup(bgra[2]), bgra[3]]
+    }
+}
+
+// Applies a table to every color channel (or to the luminance only), keeping alpha
+pub fn apply_lut_image<P: Pixel>(image: &Image<P>, lut: &Lut, mode: ChannelMode) -> Image<P> {
+    image.map(|pixel| P::from_bgra(lut.apply(pixel.to_bgra(), mode)))
+}
+
+// Applies a table to an image
+pub fn apply_lut<P: Pixel>(image: &Data<P>, lut: &Lut, mode: ChannelMode) -> Data<P> {
+    image.with_pixels(apply_lut_image(&image.pixels, lut, mode))
+}
+
+// Applies a table of its own to each color channel, keeping alpha
+pub fn apply_channel_luts_image<P: Pixel>(image: &Image<P>, luts: &ChannelLuts) -> Image<P> {
+    image.map(|pixel| P::from_bgra(luts.apply(pixel.to_bgra())))
+}
+
+// Applies a table of its own to each color channel of an image
+pub fn apply_channel_luts<P: Pixel>(image: &Data<P>, luts: &ChannelLuts) -> Data<P> {
+    image.with_pixels(apply_channel_luts_image(&image.pixels, luts))
+}
+
+// Applies a chain of adjustments in one pass
+pub fn adjust<P: Pixel>(image: &Data<P>, adjustments: &[Adjustment], mode: ChannelMode) -> Data<P> {
+    apply_lut(image, &Lut::from_adjustments(adjustments), mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Bgr;
+
+    #[test]
+    fn test_adjustments() {
+        assert_eq!(Adjustment::Brightness(0.25).apply(0.5), 0.75);
+        assert_eq!(Adjustment::Contrast {amount: 2.0, pivot: 0.25}.apply(0.5), 0.75);
+        assert_eq!(Adjustment::contrast(0.0).apply(0.9), 0.5);
+        assert!((Adjustment::Gamma(2.0).apply(0.25) - 0.5).abs() < 1e-6);
+        assert!((Adjustment::Exposure(0.0).apply(0.3) - 0.3).abs() < 1e-5);
+
+        // One stop doubles the light: middle gray (18% reflectance) goes to 36%
+        let gray = color::linear_to_srgb(0.18);
+        assert!((color::srgb_to_linear(Adjustment::Exposure(1.0).apply(gray)) - 0.36).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_composed_lut_matches_chain() {
+        let adjustments = [Adjustment::Gamma(1.8), Adjustment::Brightness(0.1), Adjustment::contrast(1.5), Adjustment::Exposure(-0.5)];
+        let lut = Lut::from_adjustments(&adjustments);
+        for value in 0..=255u8 {
+            let expected = adjustments.iter().fold(value as f32 / 255.0, |value, adjustment| adjustment.apply(value).clamp(0.0, 1.0));
+            assert!((lut.lookup(value as f32 / 255.0) - expected).abs() < 0.01, "{}", value);
+        }
+        assert_eq!(Lut::identity().then(&lut), lut);
+    }
+
+    #[test]
+    fn test_apply_modes_and_parse() {
+        let image = Image::new(1, 1, Bgr {blue: 0u8, green: 100, red: 220});
+        let brighter = Lut::from_adjustment(Adjustment::Brightness(0.2));
+        assert_eq!(apply_lut_image(&image, &brighter, ChannelMode::PerChannel).get(0, 0), Bgr {blue: 51, green: 151, red: 255});
+
+        // In luminance mode every channel moves together, even past the top of the range
+        let shifted = apply_lut_image(&image, &brighter, ChannelMode::Luminance).get(0, 0);
+        assert_eq!((shifted.green - 100, shifted.red), (51, 255));
+        assert_eq!(shifted.blue, 51);
+
+        assert_eq!(Adjustment::parse("contrast=1.2@0.4").unwrap(), Adjustment::Contrast {amount: 1.2, pivot: 0.4});
+        assert_eq!(Adjustment::parse("Exposure = -1").unwrap(), Adjustment::Exposure(-1.0));
+        assert!(Adjustment::parse("gamma=0").is_err());
+        assert!(Adjustment::parse("hue=3").is_err());
+    }
+}
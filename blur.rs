@@ -0,0 +1,230 @@
+// Blur filters: Gaussian, box and motion blur
+// Blurs average premultiplied values, so transparent pixels don't bleed their color into their neighbours
+// With Edge::Constant the outside of the image reads as transparent, so edges fade out instead of darkening
+
+use crate::convolve::{self, Kernel};
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::resample::{self, Edge, Interpolation};
+use crate::Data;
+
+// Sigma above which Gaussian blur switches to repeated box blurs, whose cost doesn't grow with the radius
+const BOX_APPROXIMATION_SIGMA: f32 = 8.0;
+
+// Number of box blurs used to approximate a Gaussian (three is within a few percent)
+const BOX_PASSES: usize = 3;
+
+// Premultiplied BGRA values of every pixel
+fn premultiplied<P: Pixel>(image: &Image<P>) -> Vec<[f32; 4]> {
+    image.data.iter().map(|pixel| resample::premultiply(pixel.to_bgra())).collect()
+}
+
+// Image built back from premultiplied BGRA values
+fn from_premultiplied<P: Pixel>(width: usize, height: usize, values: &[[f32; 4]]) -> Image<P> {
+    Image::from_vec(width, height, values.iter().map(|value| P::from_bgra(resample::unpremultiply(*value))).collect())
+}
+
+// Normalized one-dimensional Gaussian reaching three standard deviations each way
+pub fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil().max(0.0) as isize;
+    let weights: Vec<f32> = (-radius..=radius).map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f32 = weights.iter().sum();
+    weights.iter().map(|weight| weight / total).collect()
+}
+
+// Radii of box blurs that, applied one after another, come close to a Gaussian with the given sigma
+// Each box has variance (width^2 - 1) / 12, so the widths are picked to add up to sigma^2
+pub fn box_radii(sigma: f32, passes: usize) -> Vec<usize> {
+    let n = passes as f32;
+    let ideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
+    let mut lower = ideal.floor() as usize;
+    if lower.is_multiple_of(2) {
+        lower = lower.saturating_sub(1).max(1);
+    }
+    let lower_width = lower as f32;
+    let lower_passes = ((12.0 * sigma * sigma - n * lower_width * lower_width - 4.0 * n * lower_width - 3.0 * n) / (-4.0 * lower_width - 4.0)).round().clamp(0.0, n) as usize;
+    (0..passes).map(|i| if i < lower_passes { lower / 2 } else { lower / 2 + 1 }).collect()
+}
+
+// Averages every value with the radius values on either side of it along lines of the data,
+// keeping a running sum so each value costs the same whatever the radius
+// `index` gives the position in the data of the i-th value along a line
+fn box_pass(values: &[[f32; 4]], lines: usize, length: usize, radius: usize, edge: Edge, index: impl Fn(usize, usize) -> usize) -> Vec<[f32; 4]> {
+    let mut result = vec![[0.0f32; 4]; values.len()];
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    for line in 0..lines {
+        let fetch = |i: isize| match resample::edge_position(i, length, edge) {
+            Some(i) => values[index(line, i)],
+            None => [0.0; 4],
+        };
+
+        let mut sum = [0.0f32; 4];
+        for i in -(radius as isize)..=radius as isize {
+            let value = fetch(i);
+            for c in 0..4 {
+                sum[c] += value[c];
+            }
+        }
+        for i in 0..length {
+            result[index(line, i)] = sum.map(|total| total * scale);
+
+            // Slide the window one step along
+            let entering = fetch((i + radius + 1) as isize);
+            let leaving = fetch(i as isize - radius as isize);
+            for c in 0..4 {
+                sum[c] += entering[c] - leaving[c];
+            }
+        }
+    }
+    result
+}
+
+// Box blur on premultiplied values, horizontal pass then vertical pass
+pub fn box_blur_values(values: &[[f32; 4]], width: usize, height: usize, radius: usize, edge: Edge) -> Vec<[f32; 4]> {
+    let rows = box_pass(values, height, width, radius, edge, |y, x| y * width + x);
+    box_pass(&rows, width, height, radius, edge, |x, y| y * width + x)
+}
+
+// Replaces every pixel with the average of the (2 * radius + 1) square around it
+pub fn box_blur_image<P: Pixel>(image: &Image<P>, radius: usize, edge: Edge) -> Image<P> {
+    let values = box_blur_values(&premultiplied(image), image.width(), image.height(), radius, edge);
+    from_premultiplied(image.width(), image.height(), &values)
+}
+
+// Gaussian blur with the given standard deviation in pixels
+// Small sigmas use an exact separable kernel, large ones repeated box blurs
+pub fn gaussian_blur_image<P: Pixel>(image: &Image<P>, sigma: f32, edge: Edge) -> Image<P> {
+    if sigma <= 0.0 {
+        return image.clone();
+    }
+    let (width, height) = (image.width(), image.height());
+    let mut values = premultiplied(image);
+    if sigma > BOX_APPROXIMATION_SIGMA {
+        for radius in box_radii(sigma, BOX_PASSES) {
+            values = box_blur_values(&values, width, height, radius, edge);
+        }
+    }
+    else {
+        let weights = gaussian_kernel(sigma);
+        values = convolve::convolve_values(&values, width, height, &Kernel::separable(&weights, &weights), edge);
+    }
+    from_premultiplied(width, height, &values)
+}
+
+// Smears every pixel along a line of the given length in pixels, as if the camera moved during the exposure
+// The angle is counterclockwise from the horizontal as displayed, assuming the first row is the top
+pub fn motion_blur_image<P: Pixel>(image: &Image<P>, degrees: f32, length: f32, edge: Edge) -> Image<P> {
+    let (width, height) = (image.width(), image.height());
+    let samples = length.round().max(1.0) as usize;
+    if samples == 1 {
+        return image.clone();
+    }
+
+    // One sample per pixel of length, centered on the pixel, with y pointing down
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let step = (length - 1.0) / (samples - 1) as f32;
+    let offsets: Vec<(f32, f32)> = (0..samples).map(|i| {
+        let distance = i as f32 * step - (length - 1.0) / 2.0;
+        (distance * cos, -distance * sin)
+    }).collect();
+
+    let values = premultiplied(image);
+    let fetch = |x: isize, y: isize| {
+        match (resample::edge_position(x, width, edge), resample::edge_position(y, height, edge)) {
+            (Some(x), Some(y)) => values[y * width + x],
+            _ => [0.0; 4],
+        }
+    };
+
+    let blurred: Vec<[f32; 4]> = (0..width * height).map(|i| {
+        let (x, y) = ((i % width) as f32, (i / width) as f32);
+        let mut total = [0.0f32; 4];
+        for (dx, dy) in &offsets {
+            let value = resample::sample(x + dx, y + dy, Interpolation::Bilinear, fetch);
+            for c in 0..4 {
+                total[c] += value[c];
+            }
+        }
+        total.map(|value| value / samples as f32)
+    }).collect();
+    from_premultiplied(width, height, &blurred)
+}
+
+// Box blur of an image
+pub fn box_blur<P: Pixel>(image: &Data<P>, radius: usize, edge: Edge) -> Data<P> {
+    image.with_pixels(box_blur_image(&image.pixels, radius, edge))
+}
+
+// Gaussian blur of an image
+pub fn gaussian_blur<P: Pixel>(image: &Data<P>, sigma: f32, edge: Edge) -> Data<P> {
+    image.with_pixels(gaussian_blur_image(&image.pixels, sigma, edge))
+}
+
+// Motion blur with the angle as displayed
+// TGA files normally store the bottom row first, so the angle is reversed for those images
+pub fn motion_blur<P: Pixel>(image: &Data<P>, degrees: f32, length: f32, edge: Edge) -> Data<P> {
+    let degrees = if image.header.is_top_down() { degrees } else { -degrees };
+    image.with_pixels(motion_blur_image(&image.pixels, degrees, length, edge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convolve::Convolution;
+    use crate::pixel::{Bgra, Gray};
+
+    #[test]
+    fn test_box_blur_matches_convolution() {
+        let image = Image::from_fn(9, 6, |x, y| Gray {value: ((x * 53 + y * 29) % 256) as u8});
+        let kernel = Kernel::new(5, 5, vec![1.0; 25]);
+        // Edge::Constant differs on purpose: blurs read the outside as transparent rather than black
+        for edge in [Edge::Clamp, Edge::Mirror, Edge::Wrap] {
+            let convolved = convolve::convolve_image(&image, &kernel, &Convolution::new(edge));
+            assert_eq!(box_blur_image(&image, 2, edge), convolved, "{:?}", edge);
+        }
+    }
+
+    #[test]
+    fn test_box_approximation_is_close_to_gaussian() {
+        // The variances of the boxes add up to roughly sigma squared
+        let sigma = 12.0;
+        let variance: f32 = box_radii(sigma, 3).iter().map(|radius| ((2 * radius + 1).pow(2) - 1) as f32 / 12.0).sum();
+        assert!((variance.sqrt() - sigma).abs() < 0.5);
+
+        // Blurring a hard edge gives nearly the same ramp either way
+        let image = Image::from_fn(120, 1, |x, _| Gray {value: if x < 60 { 0u8 } else { 255 }});
+        let approximate = gaussian_blur_image(&image, sigma, Edge::Clamp);
+        let weights = gaussian_kernel(sigma);
+        let exact = convolve::convolve_image(&image, &Kernel::separable(&weights, &[1.0]), &Convolution::new(Edge::Clamp));
+        for (a, b) in approximate.data.iter().zip(exact.data.iter()) {
+            assert!(a.value.abs_diff(b.value) <= 6);
+        }
+    }
+
+    #[test]
+    fn test_motion_blur_follows_angle() {
+        let mut image = Image::new(9, 9, Gray {value: 0u8});
+        image.put(4, 4, Gray {value: 250});
+
+        // Horizontal streak stays in its row
+        let horizontal = motion_blur_image(&image, 0.0, 5.0, Edge::Constant);
+        assert_eq!(horizontal.get(2, 4).value, 50);
+        assert_eq!(horizontal.get(4, 3).value, 0);
+
+        // Vertical streak stays in its column
+        let vertical = motion_blur_image(&image, 90.0, 5.0, Edge::Constant);
+        assert_eq!(vertical.get(4, 6).value, 50);
+        assert_eq!(vertical.get(3, 4).value, 0);
+    }
+
+    #[test]
+    fn test_blur_keeps_color_next_to_transparency() {
+        let image = Image::from_fn(6, 1, |x, _| {
+            if x < 3 { Bgra {blue: 0u8, green: 0, red: 255, alpha: 255} } else { Bgra {blue: 0, green: 0, red: 0, alpha: 0} }
+        });
+        let blurred = gaussian_blur_image(&image, 1.0, Edge::Clamp);
+        let edge = blurred.get(3, 0);
+        assert!(edge.alpha > 0 && edge.alpha < 255);
+        assert_eq!(edge.red, 255);
+    }
+}
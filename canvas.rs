@@ -0,0 +1,214 @@
+// Operations that change the canvas size: cropping, padding and trimming borders
+
+use std::io;
+
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::Data;
+
+// Where the original image sits when the canvas is resized
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub fn parse(name: &str) -> Result<Anchor, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "top-left" => Ok(Anchor::TopLeft),
+            "top" => Ok(Anchor::Top),
+            "top-right" => Ok(Anchor::TopRight),
+            "left" => Ok(Anchor::Left),
+            "center" => Ok(Anchor::Center),
+            "right" => Ok(Anchor::Right),
+            "bottom-left" => Ok(Anchor::BottomLeft),
+            "bottom" => Ok(Anchor::Bottom),
+            "bottom-right" => Ok(Anchor::BottomRight),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown anchor: {}", name))),
+        }
+    }
+
+    // Horizontal and vertical position as fractions (0 = left/top, 1 = right/bottom)
+    fn fractions(self) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (0.0, 0.0),
+            Anchor::Top => (0.5, 0.0),
+            Anchor::TopRight => (1.0, 0.0),
+            Anchor::Left => (0.0, 0.5),
+            Anchor::Center => (0.5, 0.5),
+            Anchor::Right => (1.0, 0.5),
+            Anchor::BottomLeft => (0.0, 1.0),
+            Anchor::Bottom => (0.5, 1.0),
+            Anchor::BottomRight => (1.0, 1.0),
+        }
+    }
+
+    // The same anchor with top and bottom swapped, used for images stored bottom row first
+    fn mirrored_vertically(self) -> Anchor {
+        match self {
+            Anchor::TopLeft => Anchor::BottomLeft,
+            Anchor::Top => Anchor::Bottom,
+            Anchor::TopRight => Anchor::BottomRight,
+            Anchor::BottomLeft => Anchor::TopLeft,
+            Anchor::Bottom => Anchor::Top,
+            Anchor::BottomRight => Anchor::TopRight,
+            other => other,
+        }
+    }
+}
+
+// Copies the rectangle starting at (x, y), limited to the part that lies inside the image
+pub fn crop_image<P: Pixel>(image: &Image<P>, x: usize, y: usize, width: usize, height: usize) -> Image<P> {
+    let x = x.min(image.width());
+    let y = y.min(image.height());
+    let width = width.min(image.width() - x);
+    let height = height.min(image.height() - y);
+    image.view(x, y, width, height).to_image()
+}
+
+// Crops an image to the rectangle whose top-left corner is (x, y) as displayed,
+// updating the header's width and height
+pub fn crop<P: Pixel>(image: &Data<P>, x: usize, y: usize, width: usize, height: usize) -> Data<P> {
+    // Limit the rectangle to the image, then count rows from the bottom for bottom-up images
+    let image_height = image.pixels.height();
+    let y = y.min(image_height);
+    let height = height.min(image_height - y);
+    let y = if image.header.is_top_down() { y } else { image_height - y - height };
+    image.with_pixels(crop_image(&image.pixels, x, y, width, height))
+}
+
+// Places the image on a new canvas, filling any new area with one color
+// A smaller canvas cuts off the side of the image away from the anchor
+pub fn resize_canvas_image<P: Pixel>(image: &Image<P>, new_width: usize, new_height: usize, anchor: Anchor, fill: P) -> Image<P> {
+    let (fraction_x, fraction_y) = anchor.fractions();
+
+    // Offset of the original image on the new canvas (negative when it is cut off)
+    let offset_x = ((new_width as f32 - image.width() as f32) * fraction_x).floor() as isize;
+    let offset_y = ((new_height as f32 - image.height() as f32) * fraction_y).floor() as isize;
+
+    // Part of the original image that lands on the canvas
+    let source_x = (-offset_x).max(0) as usize;
+    let source_y = (-offset_y).max(0) as usize;
+    let target_x = offset_x.max(0) as usize;
+    let target_y = offset_y.max(0) as usize;
+    let copy_width = image.width().saturating_sub(source_x).min(new_width.saturating_sub(target_x));
+    let copy_height = image.height().saturating_sub(source_y).min(new_height.saturating_sub(target_y));
+
+    let mut canvas = Image::new(new_width, new_height, fill);
+    let source = image.view(source_x, source_y, copy_width, copy_height);
+    canvas.view_mut(target_x, target_y, copy_width, copy_height).copy_from(&source);
+    canvas
+}
+
+// Resizes the canvas with the anchor as displayed, updating the header's width and height
+pub fn resize_canvas<P: Pixel>(image: &Data<P>, new_width: usize, new_height: usize, anchor: Anchor, fill: P) -> Data<P> {
+    let anchor = if image.header.is_top_down() { anchor } else { anchor.mirrored_vertically() };
+    image.with_pixels(resize_canvas_image(&image.pixels, new_width, new_height, anchor, fill))
+}
+
+// Whether two pixels differ by at most the tolerance in every channel
+// Tolerance is a fraction of full intensity, e.g. 10.0 / 255.0 for 10 levels of an 8-bit channel
+fn matches<P: Pixel>(pixel: P, color: P, tolerance: f32) -> bool {
+    let pixel = pixel.to_bgra();
+    let color = color.to_bgra();
+    (0..4).all(|i| (pixel[i] - color[i]).abs() <= tolerance)
+}
+
+// Smallest rectangle (x, y, width, height) containing every pixel that doesn't match the border color
+// The border color defaults to the first pixel; returns None when the whole image matches it
+pub fn trim_bounds<P: Pixel>(image: &Image<P>, color: Option<P>, tolerance: f32) -> Option<(usize, usize, usize, usize)> {
+    if image.data.is_empty() {
+        return None;
+    }
+    let color = color.unwrap_or(image.data[0]);
+
+    let mut left = image.width();
+    let mut right = 0;
+    let mut top = image.height();
+    let mut bottom = 0;
+    for (y, row) in image.rows().enumerate() {
+        // First and last pixels in this row that differ from the border
+        let first = row.iter().position(|pixel| !matches(*pixel, color, tolerance));
+        if let Some(first) = first {
+            let last = row.iter().rposition(|pixel| !matches(*pixel, color, tolerance)).unwrap_or(first);
+            left = left.min(first);
+            right = right.max(last);
+            top = top.min(y);
+            bottom = y;
+        }
+    }
+
+    if top > bottom {
+        return None;
+    }
+    Some((left, top, right - left + 1, bottom - top + 1))
+}
+
+// Removes uniform borders, updating the header's width and height
+// Images that are a single color throughout are returned unchanged
+pub fn auto_trim<P: Pixel>(image: &Data<P>, color: Option<P>, tolerance: f32) -> Data<P> {
+    match trim_bounds(&image.pixels, color, tolerance) {
+        Some((x, y, width, height)) => image.with_pixels(crop_image(&image.pixels, x, y, width, height)),
+        None => image.with_pixels(image.pixels.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    // Values of an image in row-major order
+    fn values(image: &Image<Gray<u8>>) -> Vec<u8> {
+        image.data.iter().map(|pixel| pixel.value).collect()
+    }
+
+    #[test]
+    fn test_crop_limits_to_image() {
+        let image = Image::from_fn(4, 3, |x, y| Gray {value: (y * 10 + x) as u8});
+        assert_eq!(values(&crop_image(&image, 1, 1, 2, 2)), vec![11, 12, 21, 22]);
+
+        let clipped = crop_image(&image, 3, 2, 5, 5);
+        assert_eq!((clipped.width(), clipped.height()), (1, 1));
+        assert_eq!(crop_image(&image, 9, 9, 2, 2).data.len(), 0);
+    }
+
+    #[test]
+    fn test_canvas_anchors() {
+        let image = Image::new(2, 1, Gray {value: 9u8});
+        let fill = Gray {value: 0};
+        assert_eq!(values(&resize_canvas_image(&image, 4, 2, Anchor::TopLeft, fill)), vec![9, 9, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(values(&resize_canvas_image(&image, 4, 3, Anchor::Center, fill)), vec![0, 0, 0, 0, 0, 9, 9, 0, 0, 0, 0, 0]);
+        assert_eq!(values(&resize_canvas_image(&image, 3, 2, Anchor::BottomRight, fill)), vec![0, 0, 0, 0, 9, 9]);
+
+        // Shrinking the canvas keeps the anchored side
+        let numbered = Image::from_fn(4, 1, |x, _| Gray {value: x as u8});
+        assert_eq!(values(&resize_canvas_image(&numbered, 2, 1, Anchor::Right, fill)), vec![2, 3]);
+
+        assert_eq!(Anchor::parse("Bottom-Left").unwrap(), Anchor::BottomLeft);
+        assert!(Anchor::parse("middle").is_err());
+    }
+
+    #[test]
+    fn test_trim_with_tolerance() {
+        // Slightly noisy white border around a dark 2x2 block
+        let image = Image::from_fn(6, 5, |x, y| {
+            if (2..4).contains(&x) && (1..3).contains(&y) { Gray {value: 20u8} } else { Gray {value: 250 + ((x + y) % 3) as u8} }
+        });
+        assert_eq!(trim_bounds(&image, None, 5.0 / 255.0), Some((2, 1, 2, 2)));
+
+        // With an explicit border color and zero tolerance, only exact matches are trimmed
+        assert_eq!(trim_bounds(&image, Some(Gray {value: 250}), 0.0), Some((0, 0, 6, 5)));
+
+        // A single color image has nothing to keep
+        assert_eq!(trim_bounds(&Image::new(3, 3, Gray {value: 1u8}), None, 0.0), None);
+    }
+}
@@ -0,0 +1,73 @@
+// Channel storage types that pixels can be built from (u8, u16 and f32)
+
+use std::fmt;
+
+// Trait implemented by every type that can store a single color channel
+// Values are converted through a normalized f32 (0.0 = black, 1.0 = full intensity)
+pub trait Channel: Copy + Default + PartialEq + fmt::Debug + fmt::Display {
+    // Converts the stored value to a normalized f32
+    fn to_f32(self) -> f32;
+
+    // Builds a channel value from a normalized f32
+    // Integer channels round and clamp here, float channels keep the value as is
+    fn from_f32(value: f32) -> Self;
+}
+
+// 8-bit channels, as stored in TGA files
+impl Channel for u8 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 255.0
+    }
+
+    fn from_f32(value: f32) -> Self {
+        (value * 255.0).round().clamp(0.0, 255.0) as u8
+    }
+}
+
+// 16-bit channels, every 8-bit value maps exactly onto value * 257
+impl Channel for u16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 65535.0
+    }
+
+    fn from_f32(value: f32) -> Self {
+        (value * 65535.0).round().clamp(0.0, 65535.0) as u16
+    }
+}
+
+// Floating point channels, never quantized or clamped until saved
+impl Channel for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_u8_round_trips_losslessly() {
+        // Every 8-bit value must survive a trip through the wider channel types
+        for value in 0..=255u8 {
+            assert_eq!(u8::from_f32(u16::from_f32(value.to_f32()).to_f32()), value);
+            assert_eq!(u8::from_f32(value.to_f32()), value);
+            assert_eq!(u16::from_f32(value.to_f32()), value as u16 * 257);
+        }
+    }
+
+    #[test]
+    fn test_integer_channels_clamp() {
+        // Out of range values saturate instead of wrapping
+        assert_eq!(u8::from_f32(1.5), 255);
+        assert_eq!(u8::from_f32(-0.5), 0);
+        assert_eq!(u16::from_f32(2.0), 65535);
+
+        // Float channels keep out of range values until they are saved
+        assert_eq!(f32::from_f32(1.5), 1.5);
+    }
+}
@@ -0,0 +1,647 @@
+// Command line interface: runs one operation on a TGA file
+// Usage: image-processor <command> <input.tga> <output.tga> [arguments...]
+
+use std::fs;
+use std::io;
+
+use crate::adjust::{self, Adjustment, Lut};
+use crate::color::{self, DeltaE};
+use crate::blur;
+use crate::canvas::{self, Anchor};
+use crate::convolve::{self, Convolution, Kernel};
+use crate::cube::{self, CubeInterpolation, CubeLut, GradeStep};
+use crate::curves;
+use crate::edges::{self, EdgeOutput, Operator};
+use crate::geometry::{self, Orientation};
+use crate::histogram;
+use crate::hue::{self, HueModel, HueRange, HueSaturation};
+use crate::mixer::{self, ChannelMixer};
+use crate::morphology::{self, Element, Operation};
+use crate::pixel::{Bgr, Bgra, ChannelMode, Pixel};
+use crate::rank::{self, Rank};
+use crate::resample::{Edge, Interpolation};
+use crate::resize::{self, Filter, Size};
+use crate::sharpen;
+use crate::smooth;
+use crate::rotate::{self, Canvas};
+use crate::split::{self, Component};
+use crate::swizzle::{self, Swizzle};
+use crate::warp::{self, Transform};
+use crate::{read_tga_as, write_tga, Data};
+
+const USAGE: &str = "\
+Usage: image-processor [<command> <input.tga> <output.tga> [arguments...]]
+Without a command every part of the project is run.
+
+Commands:
+  orient <input.tga> <output.tga> <orientation>
+      Flip or turn the image as displayed: flip-horizontal, flip-vertical, rotate-90, rotate-180,
+      rotate-270 (clockwise), transpose or transverse
+  rotate <input.tga> <output.tga> <degrees> [nearest|bilinear|bicubic] [--crop]
+      Rotate clockwise by any angle, interpolating bilinearly unless named, growing the canvas to
+      fit unless --crop is given; uncovered corners are transparent in 32-bit files, black otherwise
+  resize <input.tga> <output.tga> <size> [<filter>]
+      Resize to <width>x<height>, to fit within fit:<width>x<height> or by a factor such as 0.5, with
+      the nearest, bilinear, catmull-rom (the default), mitchell or lanczos3 filter
+  crop <input.tga> <output.tga> <x> <y> <width> <height>
+      Cut out the rectangle whose top-left corner is at x, y as displayed
+  canvas <input.tga> <output.tga> <width>x<height> [<anchor>] [--fill <color>]
+      Grow or shrink the canvas around the image, keeping it at the center or at top-left, top,
+      top-right, left, right, bottom-left, bottom or bottom-right, and filling new area with
+      <red>,<green>,<blue>[,<alpha>] (0 to 255, transparent black unless given)
+  trim <input.tga> <output.tga> [<tolerance>] [--color <color>]
+      Remove borders of one color, the first pixel's unless given as for canvas, allowing channels
+      to differ by up to tolerance levels (0 to 255)
+  warp <input.tga> <output.tga> <step>... [<interpolation>] [--size <width>x<height>] [--edge <edge>]
+      Transform the image as displayed by translate=<dx>,<dy>, scale=<factor>[,<factor y>],
+      rotate=<degrees>, shear=<x>,<y> and matrix=<a>,...,<i> (3x3 row by row, or 6 numbers for an
+      affine matrix) in order about the top-left corner, onto a canvas of the same size unless
+      --size is given, reading outside the image with clamp, wrap, mirror or constant (the default,
+      transparent in 32-bit files and black otherwise) edges
+  rectify <input.tga> <output.tga> <x>,<y> <x>,<y> <x>,<y> <x>,<y> <width>x<height> [<interpolation>]
+      Straighten the region with the given top-left, top-right, bottom-right and bottom-left corners
+      as displayed into a new image of the given size
+  convolve <input.tga> <output.tga> <row>... [--edge <edge>] [--no-normalize] [--bias <levels>] [--alpha]
+      Convolve with a kernel given as rows of comma-separated weights, top row first (e.g. 0,-1,0
+      -1,5,-1 0,-1,0), with odd width and height, reading outside the image as for warp (clamp
+      unless given), dividing by the weights' sum unless --no-normalize is given, adding a bias of
+      0 to 255 levels and convolving alpha too with --alpha
+  blur <input.tga> <output.tga> <kind> [--edge <edge>]
+      Blur with gaussian <sigma>, box <radius> or motion <degrees> <length> (the angle counterclockwise
+      from the horizontal, lengths and sigma in pixels), reading outside the image as for convolve
+  sharpen <input.tga> <output.tga> <kind> [--edge <edge>]
+      Sharpen with unsharp <amount> <radius> [<threshold>], laplacian <strength> or high-pass <radius>
+      (the detail alone, centered on gray, to overlay on the original), with radii in pixels and the
+      threshold in levels (0 to 255), reading outside the image as for convolve
+  edges <input.tga> <output.tga> [sobel|prewitt|scharr] [--threshold <levels>] [--direction] [--edge <edge>]
+      Show the strength of edges in the luminance using the Sobel operator unless named, white where
+      it reaches the threshold (0 to 255) and black elsewhere if given, or with --direction the way
+      brightness rises from black (pointing left, turning counterclockwise) to white, reading outside the
+      image as for convolve
+  canny <input.tga> <output.tga> <sigma> <low> <high>
+      Find one pixel wide edges with the Canny detector, smoothing with a Gaussian of the given sigma
+      and following edges from those reaching the high threshold through those above the low one
+      (0 to 255)
+  rank <input.tga> <output.tga> <radius> [<rank>] [--edge <edge>]
+      Replace every color channel with the median (unless named), minimum, maximum or
+      percentile=<percent> of the (2 * radius + 1) square around it, reading outside the image as
+      for convolve, or only inside it with constant
+  smooth <input.tga> <output.tga> <kind> [--guide <guide.tga>] [--luminance] [--edge <edge>]
+      Smooth while keeping edges with bilateral <sigma> <range> (a Gaussian of sigma pixels, ignoring
+      neighbours differing by much more than range levels) or guided <radius> <contrast> (keeping
+      edges of the guide, the image itself unless given, above contrast levels), on every color
+      channel or with --luminance the brightness only, reading outside the image as for convolve
+  morph <input.tga> <output.tga> <operation> [<element>]
+      Erode, dilate, open, close, or take the gradient, top-hat or black-hat of every color channel
+      with an element given as <shape>:<width>x<height> (odd), the shape being rectangle, ellipse or
+      cross, laid out as displayed (rectangle:3x3 unless given)
+  mix <input.tga> <output.tga> <matrix>
+      Mix channels with 12 (3x4, RGB) or 20 (4x5, RGBA) numbers given row by row,
+      each row holding the red, green, blue (and alpha) gains followed by an offset, or grayscale
+      for every color channel set to the luma
+  adjust <input.tga> <output.tga> <adjustment>... [--luminance]
+      Apply brightness=<amount>, contrast=<amount>[@<pivot>], gamma=<gamma> and exposure=<stops>
+      in order, to every color channel or with --luminance to the brightness only
+  tone <input.tga> <output.tga> <settings.txt>
+      Apply the levels and curves in a settings file, one per line:
+      levels <rgb|red|green|blue> <in black> <in white> <gamma> [<out black> <out white>]
+      curve <rgb|red|green|blue> <input>,<output>...   (values 0 to 255)
+  hue <input.tga> <output.tga> [hue=<degrees>] [saturation=<amount>] [lightness=<amount>] [range=<range>] [model=<model>]
+      Rotate hues and change saturation and lightness (amounts -1 to 1), optionally only within a
+      range: reds, yellows, greens, cyans, blues, magentas or <center>,<width>,<feather> in degrees
+      Saturation and lightness are measured in the hsl model unless model=hsv is given
+  cube <input.tga> <output.tga> <lut.cube> [--trilinear]
+      Grade with a 1D or 3D .cube LUT, read with tetrahedral interpolation unless --trilinear is given
+  bake <output.cube> <size> [<adjustment>...] [--luminance] [--tone <settings.txt>] [--mix <matrix>]
+       [--hue <settings>] [--swizzle <spec>]
+      Save a 3D LUT with size entries per channel doing the given steps in order: adjustments as for
+      adjust (--luminance applies to the ones after it), tone settings files, channel mixer matrices,
+      hue settings as for hue given as one argument (e.g. 'hue=30 range=reds') and swizzle specs
+  histogram <input.tga> [<channel>...] [--csv <counts.csv>] [--stats-csv <stats.csv>] [--image <histogram.tga>]
+      Print min, max, mean, median, standard deviation and percentiles of each channel (red, green,
+      blue and luma unless named), optionally saving the counts, the statistics and a chart
+  compare <first.tga> <second.tga> [cie1976|ciede2000] [--d50]
+      Print the mean and largest color difference (Delta E, CIEDE2000 unless named) between two
+      images of the same size as displayed, and the share of pixels differing noticeably (above
+      2.3), measured in CIELAB with the D65 white point or with --d50 the D50 one used in print
+  swizzle <input.tga> <output.tga> <spec>
+      Reorder, duplicate, zero or fill channels, e.g. rrr1, g0b1 or bgra->rgba
+  merge <output.tga> <channel>=<input.tga>[:<channel>]...
+      Build an image from channels of other images of the same size, each written to a red, green,
+      blue, alpha, hue, saturation, value, lab-l, lab-a, lab-b, lch-l, lch-c or lch-h channel from
+      the named channel of its input (luma unless given), e.g. red=r.tga green=g.tga
+      blue=photo.tga:blue; the result has the bit depth of the first input
+  raw <input.tga> <output.raw> <order>
+      Write the pixels with no header, top row first, channels in the given order (e.g. rgba, rgb1)";
+
+// Error for a command line that can't be run, with the usage text appended
+fn usage_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("{}\n\n{}", message, USAGE))
+}
+
+// Reads an image with its alpha channel, if it has one
+fn load(path: &str) -> Result<Data<Bgra>, io::Error> {
+    read_tga_as::<Bgra>(path)
+}
+
+// Writes an image in the same bit depth it was read with
+fn save(path: &str, image: Data<Bgra>) -> Result<(), io::Error> {
+    if image.header.image_details[8] == 32 {
+        write_tga(path, image)
+    }
+    else {
+        write_tga(path, image.convert::<Bgr>())
+    }
+}
+
+// Input path, output path and the remaining arguments of a command
+fn paths(arguments: &[String]) -> Result<(&str, &str, &[String]), io::Error> {
+    match arguments {
+        [input, output, rest @ ..] => Ok((input, output, rest)),
+        _ => Err(usage_error("Missing input or output file")),
+    }
+}
+
+// A number given on the command line
+fn number(text: &str) -> Result<f32, io::Error> {
+    text.trim().parse().map_err(|_| usage_error(&format!("Invalid number: {}", text)))
+}
+
+// Parses a whole number such as a pixel position or size
+fn whole_number(text: &str) -> Result<usize, io::Error> {
+    text.trim().parse().map_err(|_| usage_error(&format!("Invalid whole number: {}", text)))
+}
+
+// Parses a color given as red,green,blue or red,green,blue,alpha from 0 to 255
+fn color(text: &str) -> Result<Bgra, io::Error> {
+    let invalid = || usage_error(&format!("Invalid color: {}", text));
+    let values = text.split(',').map(|value| value.trim().parse::<u8>().map_err(|_| invalid())).collect::<Result<Vec<u8>, io::Error>>()?;
+    match values.as_slice() {
+        [red, green, blue] => Ok(Bgra {blue: *blue, green: *green, red: *red, alpha: 255}),
+        [red, green, blue, alpha] => Ok(Bgra {blue: *blue, green: *green, red: *red, alpha: *alpha}),
+        _ => Err(invalid()),
+    }
+}
+
+// Parses a position given as <x>,<y>
+fn point(text: &str) -> Result<(f64, f64), io::Error> {
+    match text.split_once(',') {
+        Some((x, y)) => Ok((number(x)? as f64, number(y)? as f64)),
+        None => Err(usage_error(&format!("Invalid position: {}", text))),
+    }
+}
+
+// Parses a size given as <width>x<height>
+fn dimensions(text: &str) -> Result<(usize, usize), io::Error> {
+    match Size::parse(text)? {
+        Size::Exact(width, height) => Ok((width, height)),
+        _ => Err(usage_error(&format!("Invalid size, expected <width>x<height>: {}", text))),
+    }
+}
+
+// Splits off a trailing --edge <edge>, reading outside the image with clamp when there is none
+fn trailing_edge(arguments: &[String]) -> Result<(&[String], Edge), io::Error> {
+    match arguments {
+        [rest @ .., flag, edge] if flag == "--edge" => Ok((rest, Edge::parse(edge)?)),
+        _ => Ok((arguments, Edge::Clamp)),
+    }
+}
+
+// Hue/saturation settings from name=value arguments
+fn hue_saturation(arguments: &[String]) -> Result<HueSaturation, io::Error> {
+    let mut adjustment = HueSaturation::identity();
+    for argument in arguments {
+        let Some((name, value)) = argument.split_once('=') else {
+            return Err(usage_error(&format!("Expected name=value: {}", argument)));
+        };
+        match name {
+            "hue" => adjustment.hue = number(value)?,
+            "saturation" => adjustment.saturation = number(value)?,
+            "lightness" => adjustment.lightness = number(value)?,
+            "range" => adjustment.range = Some(match value.split(',').collect::<Vec<&str>>().as_slice() {
+                [center, width, feather] => HueRange {center: number(center)?, width: number(width)?, feather: number(feather)?},
+                _ => HueRange::named(value)?,
+            }),
+            "model" => adjustment.model = HueModel::parse(value)?,
+            _ => return Err(usage_error(&format!("Unknown hue setting: {}", name))),
+        }
+    }
+    Ok(adjustment)
+}
+
+// Runs the command named by the first argument
+pub fn run(arguments: &[String]) -> Result<(), io::Error> {
+    let (command, arguments) = match arguments.split_first() {
+        Some((command, arguments)) => (command.as_str(), arguments),
+        None => return Err(usage_error("Missing command")),
+    };
+
+    match command {
+        "orient" => {
+            let (input, output, rest) = paths(arguments)?;
+            let [orientation] = rest else {
+                return Err(usage_error("orient needs one orientation"));
+            };
+            save(output, geometry::reorient(&load(input)?, Orientation::parse(orientation)?))
+        }
+        "rotate" => {
+            let (input, output, rest) = paths(arguments)?;
+            let Some((degrees, rest)) = rest.split_first() else {
+                return Err(usage_error("rotate needs an angle"));
+            };
+            let mut interpolation = Interpolation::Bilinear;
+            let mut canvas = Canvas::Expand;
+            for argument in rest {
+                match argument.as_str() {
+                    "--crop" => canvas = Canvas::Crop,
+                    _ => interpolation = Interpolation::parse(argument)?,
+                }
+            }
+            save(output, rotate::rotate(&load(input)?, number(degrees)?, interpolation, canvas, Bgra::default()))
+        }
+        "resize" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (size, filter) = match rest {
+                [size] => (Size::parse(size)?, Filter::CatmullRom),
+                [size, filter] => (Size::parse(size)?, Filter::parse(filter)?),
+                _ => return Err(usage_error("resize needs a size and optionally a filter")),
+            };
+            save(output, resize::resize(&load(input)?, size, filter))
+        }
+        "crop" => {
+            let (input, output, rest) = paths(arguments)?;
+            let [x, y, width, height] = rest else {
+                return Err(usage_error("crop needs x, y, width and height"));
+            };
+            save(output, canvas::crop(&load(input)?, whole_number(x)?, whole_number(y)?, whole_number(width)?, whole_number(height)?))
+        }
+        "canvas" => {
+            let (input, output, rest) = paths(arguments)?;
+            let Some((size, rest)) = rest.split_first() else {
+                return Err(usage_error("canvas needs a size"));
+            };
+            let (width, height) = dimensions(size)?;
+            let mut anchor = Anchor::Center;
+            let mut fill = Bgra::default();
+            let mut rest = rest.iter();
+            while let Some(argument) = rest.next() {
+                match argument.as_str() {
+                    "--fill" => match rest.next() {
+                        Some(value) => fill = color(value)?,
+                        None => return Err(usage_error("--fill needs a color")),
+                    },
+                    _ => anchor = Anchor::parse(argument)?,
+                }
+            }
+            save(output, canvas::resize_canvas(&load(input)?, width, height, anchor, fill))
+        }
+        "trim" => {
+            let (input, output, rest) = paths(arguments)?;
+            let mut tolerance = 0.0;
+            let mut border = None;
+            let mut rest = rest.iter();
+            while let Some(argument) = rest.next() {
+                match argument.as_str() {
+                    "--color" => match rest.next() {
+                        Some(value) => border = Some(color(value)?),
+                        None => return Err(usage_error("--color needs a color")),
+                    },
+                    _ => tolerance = number(argument)? / 255.0,
+                }
+            }
+            save(output, canvas::auto_trim(&load(input)?, border, tolerance))
+        }
+        "warp" => {
+            let (input, output, rest) = paths(arguments)?;
+            let image = load(input)?;
+            let mut transform = Transform::identity();
+            let mut interpolation = Interpolation::Bilinear;
+            let mut size = (image.pixels.width(), image.pixels.height());
+            let mut edge = Edge::Constant;
+            let mut rest = rest.iter();
+            while let Some(argument) = rest.next() {
+                match argument.as_str() {
+                    "--size" | "--edge" => {
+                        let Some(value) = rest.next() else {
+                            return Err(usage_error(&format!("{} needs a value", argument)));
+                        };
+                        match argument.as_str() {
+                            "--size" => size = dimensions(value)?,
+                            _ => edge = Edge::parse(value)?,
+                        }
+                    }
+                    _ if argument.contains('=') => transform = transform.then(&Transform::parse(argument)?),
+                    _ => interpolation = Interpolation::parse(argument)?,
+                }
+            }
+            save(output, warp::warp(&image, &transform, size.0, size.1, interpolation, edge, Bgra::default()))
+        }
+        "rectify" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (corners, size, interpolation) = match rest {
+                [a, b, c, d, size] => ([a, b, c, d], size, Interpolation::Bilinear),
+                [a, b, c, d, size, interpolation] => ([a, b, c, d], size, Interpolation::parse(interpolation)?),
+                _ => return Err(usage_error("rectify needs four corners, a size and optionally an interpolation")),
+            };
+            let corners = [point(corners[0])?, point(corners[1])?, point(corners[2])?, point(corners[3])?];
+            let (width, height) = dimensions(size)?;
+            save(output, warp::rectify(&load(input)?, corners, width, height, interpolation, Bgra::default()))
+        }
+        "convolve" => {
+            let (input, output, rest) = paths(arguments)?;
+            let mut rows = Vec::new();
+            let mut convolution = Convolution::new(Edge::Clamp);
+            let mut rest = rest.iter();
+            while let Some(argument) = rest.next() {
+                match argument.as_str() {
+                    "--no-normalize" => convolution.normalize = false,
+                    "--alpha" => convolution.include_alpha = true,
+                    "--edge" | "--bias" => {
+                        let Some(value) = rest.next() else {
+                            return Err(usage_error(&format!("{} needs a value", argument)));
+                        };
+                        match argument.as_str() {
+                            "--edge" => convolution.edge = Edge::parse(value)?,
+                            _ => convolution.bias = number(value)? / 255.0,
+                        }
+                    }
+                    _ => rows.push(argument),
+                }
+            }
+            save(output, convolve::convolve(&load(input)?, &Kernel::parse(&rows)?, &convolution))
+        }
+        "blur" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (rest, edge) = trailing_edge(rest)?;
+            let image = load(input)?;
+            let blurred = match rest {
+                [kind, sigma] if kind == "gaussian" => blur::gaussian_blur(&image, number(sigma)?, edge),
+                [kind, radius] if kind == "box" => blur::box_blur(&image, whole_number(radius)?, edge),
+                [kind, degrees, length] if kind == "motion" => blur::motion_blur(&image, number(degrees)?, number(length)?, edge),
+                _ => return Err(usage_error("blur needs gaussian <sigma>, box <radius> or motion <degrees> <length>")),
+            };
+            save(output, blurred)
+        }
+        "sharpen" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (rest, edge) = trailing_edge(rest)?;
+            let image = load(input)?;
+            let sharpened = match rest {
+                [kind, amount, radius] if kind == "unsharp" => sharpen::unsharp_mask(&image, number(amount)?, number(radius)?, 0.0, edge),
+                [kind, amount, radius, threshold] if kind == "unsharp" => {
+                    sharpen::unsharp_mask(&image, number(amount)?, number(radius)?, number(threshold)? / 255.0, edge)
+                }
+                [kind, strength] if kind == "laplacian" => sharpen::laplacian_sharpen(&image, number(strength)?, edge),
+                [kind, radius] if kind == "high-pass" => sharpen::high_pass(&image, number(radius)?, edge),
+                _ => return Err(usage_error("sharpen needs unsharp <amount> <radius> [<threshold>], laplacian <strength> or high-pass <radius>")),
+            };
+            save(output, sharpened)
+        }
+        "edges" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (rest, edge) = trailing_edge(rest)?;
+            let mut operator = Operator::Sobel;
+            let mut edge_output = EdgeOutput::Grayscale;
+            let mut direction = false;
+            let mut rest = rest.iter();
+            while let Some(argument) = rest.next() {
+                match argument.as_str() {
+                    "--direction" => direction = true,
+                    "--threshold" => match rest.next() {
+                        Some(value) => edge_output = EdgeOutput::Binary(number(value)? / 255.0),
+                        None => return Err(usage_error("--threshold needs a value")),
+                    },
+                    _ => operator = Operator::parse(argument)?,
+                }
+            }
+            let image = load(input)?;
+            if direction {
+                save(output, edges::edge_direction(&image, operator, edge))
+            }
+            else {
+                save(output, edges::edge_magnitude(&image, operator, edge_output, edge))
+            }
+        }
+        "canny" => {
+            let (input, output, rest) = paths(arguments)?;
+            let [sigma, low, high] = rest else {
+                return Err(usage_error("canny needs a sigma and low and high thresholds"));
+            };
+            save(output, edges::canny(&load(input)?, number(sigma)?, number(low)? / 255.0, number(high)? / 255.0))
+        }
+        "rank" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (rest, edge) = trailing_edge(rest)?;
+            let (radius, rank) = match rest {
+                [radius] => (whole_number(radius)?, Rank::Median),
+                [radius, rank] => (whole_number(radius)?, Rank::parse(rank)?),
+                _ => return Err(usage_error("rank needs a radius and optionally a rank")),
+            };
+            save(output, rank::rank_filter(&load(input)?, radius, rank, edge))
+        }
+        "smooth" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (rest, edge) = trailing_edge(rest)?;
+            let mut values = Vec::new();
+            let mut guide = None;
+            let mut mode = ChannelMode::PerChannel;
+            let mut rest = rest.iter();
+            while let Some(argument) = rest.next() {
+                match argument.as_str() {
+                    "--luminance" => mode = ChannelMode::Luminance,
+                    "--guide" => match rest.next() {
+                        Some(path) => guide = Some(load(path)?),
+                        None => return Err(usage_error("--guide needs a file")),
+                    },
+                    _ => values.push(argument.as_str()),
+                }
+            }
+            let image = load(input)?;
+            if guide.as_ref().is_some_and(|guide| (guide.pixels.width(), guide.pixels.height()) != (image.pixels.width(), image.pixels.height())) {
+                return Err(usage_error("The guide must be the same size as the image"));
+            }
+            let smoothed = match values.as_slice() {
+                ["bilateral", sigma, range] => smooth::bilateral_filter(&image, number(sigma)?, number(range)? / 255.0, mode, edge),
+                ["guided", radius, contrast] => {
+                    let epsilon = (number(contrast)? / 255.0).powi(2);
+                    smooth::guided_filter(&image, guide.as_ref().unwrap_or(&image), whole_number(radius)?, epsilon, mode, edge)
+                }
+                _ => return Err(usage_error("smooth needs bilateral <sigma> <range> or guided <radius> <contrast>")),
+            };
+            save(output, smoothed)
+        }
+        "morph" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (operation, element) = match rest {
+                [operation] => (Operation::parse(operation)?, Element::rectangle(3, 3)),
+                [operation, element] => (Operation::parse(operation)?, Element::parse(element)?),
+                _ => return Err(usage_error("morph needs an operation and optionally an element")),
+            };
+            save(output, morphology::morphology(&load(input)?, operation, &element))
+        }
+        "mix" => {
+            let (input, output, rest) = paths(arguments)?;
+            let mixer = ChannelMixer::parse(&rest.join(" "))?;
+            save(output, mixer::mix_channels(&load(input)?, &mixer))
+        }
+        "adjust" => {
+            let (input, output, rest) = paths(arguments)?;
+            let mut mode = ChannelMode::PerChannel;
+            let mut adjustments = Vec::new();
+            for argument in rest {
+                match argument.as_str() {
+                    "--luminance" => mode = ChannelMode::Luminance,
+                    _ => adjustments.push(Adjustment::parse(argument)?),
+                }
+            }
+            save(output, adjust::adjust(&load(input)?, &adjustments, mode))
+        }
+        "tone" => {
+            let (input, output, rest) = paths(arguments)?;
+            let [settings] = rest else {
+                return Err(usage_error("tone needs one settings file"));
+            };
+            save(output, adjust::apply_channel_luts(&load(input)?, &curves::load_settings(settings)?))
+        }
+        "hue" => {
+            let (input, output, rest) = paths(arguments)?;
+            save(output, hue::hue_saturation(&load(input)?, &hue_saturation(rest)?))
+        }
+        "cube" => {
+            let (input, output, rest) = paths(arguments)?;
+            let (lut, interpolation) = match rest {
+                [lut] => (lut, CubeInterpolation::Tetrahedral),
+                [lut, flag] if flag == "--trilinear" => (lut, CubeInterpolation::Trilinear),
+                _ => return Err(usage_error("cube needs one .cube file and optionally --trilinear")),
+            };
+            save(output, cube::apply_cube(&load(input)?, &CubeLut::load(lut)?, interpolation))
+        }
+        "bake" => {
+            let [output, size, rest @ ..] = arguments else {
+                return Err(usage_error("Missing output file or LUT size"));
+            };
+            let size = match size.parse::<usize>() {
+                Ok(size) if (2..=256).contains(&size) => size,
+                _ => return Err(usage_error("LUT size must be from 2 to 256")),
+            };
+            let mut mode = ChannelMode::PerChannel;
+            let mut steps = Vec::new();
+            let mut rest = rest.iter();
+            while let Some(argument) = rest.next() {
+                match argument.as_str() {
+                    "--luminance" => mode = ChannelMode::Luminance,
+                    "--tone" | "--mix" | "--hue" | "--swizzle" => {
+                        let Some(value) = rest.next() else {
+                            return Err(usage_error(&format!("{} needs a value", argument)));
+                        };
+                        steps.push(match argument.as_str() {
+                            "--tone" => GradeStep::ChannelLuts(Box::new(curves::load_settings(value)?)),
+                            "--mix" => GradeStep::Mixer(ChannelMixer::parse(value)?),
+                            "--hue" => {
+                                let settings: Vec<String> = value.split_whitespace().map(String::from).collect();
+                                GradeStep::HueSaturation(hue_saturation(&settings)?)
+                            }
+                            _ => GradeStep::Swizzle(Swizzle::parse(value)?),
+                        });
+                    }
+                    _ => steps.push(GradeStep::Lut(Box::new(Lut::from_adjustment(Adjustment::parse(argument)?)), mode)),
+                }
+            }
+            cube::bake(&steps, size).save(output)
+        }
+        "histogram" => {
+            let Some((input, rest)) = arguments.split_first() else {
+                return Err(usage_error("Missing input file"));
+            };
+            let mut components = Vec::new();
+            let mut outputs = Vec::new();
+            let mut rest = rest.iter();
+            while let Some(argument) = rest.next() {
+                match argument.as_str() {
+                    "--csv" | "--stats-csv" | "--image" => match rest.next() {
+                        Some(path) => outputs.push((argument.as_str(), path)),
+                        None => return Err(usage_error(&format!("{} needs a file", argument))),
+                    },
+                    _ => components.push(Component::parse(argument)?),
+                }
+            }
+            if components.is_empty() {
+                components = vec![Component::Red, Component::Green, Component::Blue, Component::Luma];
+            }
+
+            let histograms = histogram::histograms(&load(input)?, &components);
+            print!("{}", histogram::stats_text(&histograms));
+            for (kind, path) in outputs {
+                match kind {
+                    "--csv" => fs::write(path, histogram::counts_csv(&histograms))?,
+                    "--stats-csv" => fs::write(path, histogram::stats_csv(&histograms))?,
+                    _ => write_tga(path, histogram::render_histograms(&histograms, 100))?,
+                }
+            }
+            Ok(())
+        }
+        "compare" => {
+            let [first, second, rest @ ..] = arguments else {
+                return Err(usage_error("compare needs two input files"));
+            };
+            let mut formula = DeltaE::Ciede2000;
+            let mut to_lab: fn([f32; 3]) -> [f32; 3] = color::rgb_to_lab;
+            for argument in rest {
+                match argument.as_str() {
+                    "--d50" => to_lab = color::rgb_to_lab_d50,
+                    _ => formula = DeltaE::parse(argument)?,
+                }
+            }
+            let (first, second) = (load(first)?, load(second)?);
+            if !first.pixels.same_dimensions(&second.pixels) {
+                return Err(usage_error("Images have different dimensions"));
+            }
+
+            // Line the second image up with the first as displayed
+            let second = if first.header.is_top_down() == second.header.is_top_down() { second.pixels } else { second.pixels.flip_vertical() };
+            let lab = |pixel: &Bgra| {
+                let [blue, green, red, _] = pixel.to_bgra();
+                to_lab([red, green, blue])
+            };
+            let differences: Vec<f32> = first.pixels.data.iter().zip(&second.data).map(|(a, b)| formula.between(lab(a), lab(b))).collect();
+            let count = differences.len().max(1) as f32;
+            let mean = differences.iter().sum::<f32>() / count;
+            let largest = differences.iter().copied().fold(0.0, f32::max);
+            let noticeable = differences.iter().filter(|&&difference| difference > 2.3).count() as f32 / count;
+            println!("mean {:.3}\nlargest {:.3}\nnoticeable {:.2}%", mean, largest, noticeable * 100.0);
+            Ok(())
+        }
+        "swizzle" => {
+            let (input, output, rest) = paths(arguments)?;
+            let swizzle = Swizzle::parse(&rest.join(""))?;
+            save(output, swizzle::swizzle(&load(input)?, &swizzle))
+        }
+        "merge" => {
+            let Some((output, rest)) = arguments.split_first() else {
+                return Err(usage_error("Missing output file"));
+            };
+            let mut images = Vec::new();
+            let mut channels = Vec::new();
+            for argument in rest {
+                let Some((target, source)) = argument.split_once('=') else {
+                    return Err(usage_error(&format!("Expected <channel>=<input.tga>: {}", argument)));
+                };
+                let (path, read) = source.rsplit_once(':').unwrap_or((source, "luma"));
+                images.push(load(path)?);
+                channels.push((read, target));
+            }
+            let inputs: Vec<(&Data<Bgra>, &str, &str)> = images.iter().zip(channels).map(|(image, (read, target))| (image, read, target)).collect();
+            save(output, split::merge_channels_by_name(&inputs)?)
+        }
+        "raw" => {
+            let (input, output, rest) = paths(arguments)?;
+            let order = swizzle::parse_order(&rest.join(""))?;
+            swizzle::export_raw(output, &load(input)?, &order)
+        }
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(usage_error(&format!("Unknown command: {}", command))),
+    }
+}
@@ -0,0 +1,400 @@
+// Color model conversions
+// Colors are normalized [red, green, blue] arrays in sRGB, the way TGA files store them
+// Besides sRGB: linear RGB, CIE XYZ, CIELAB and LCh, with Bradford adaptation from D65 to D50
+
+use std::io;
+
+// White point of the D65 illuminant used by sRGB, in XYZ
+pub const D65: [f32; 3] = [0.95047, 1.0, 1.08883];
+
+// White point of the D50 illuminant used by print and ICC profiles, in XYZ
+pub const D50: [f32; 3] = [0.96422, 1.0, 0.82521];
+
+// Bradford cone response matrix and its inverse, for chromatic adaptation
+const BRADFORD: [[f32; 3]; 3] = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+const BRADFORD_INVERSE: [[f32; 3]; 3] = [
+    [0.9869929, -0.1470543, 0.1599627],
+    [0.4323053, 0.5183603, 0.0492912],
+    [-0.0085287, 0.0400428, 0.9684867],
+];
+
+fn multiply(matrix: &[[f32; 3]; 3], vector: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+// Hue in degrees (0 to 360) of a color with the given largest channel and chroma (largest minus smallest)
+fn hue(rgb: [f32; 3], max: f32, chroma: f32) -> f32 {
+    let [red, green, blue] = rgb;
+    if chroma == 0.0 {
+        0.0
+    }
+    else if max == red {
+        60.0 * ((green - blue) / chroma).rem_euclid(6.0)
+    }
+    else if max == green {
+        60.0 * ((blue - red) / chroma + 2.0)
+    }
+    else {
+        60.0 * ((red - green) / chroma + 4.0)
+    }
+}
+
+// Color with a hue and chroma whose smallest channel is zero
+fn hue_chroma_to_rgb(hue: f32, chroma: f32) -> [f32; 3] {
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let second = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    match sector as u32 {
+        0 => [chroma, second, 0.0],
+        1 => [second, chroma, 0.0],
+        2 => [0.0, chroma, second],
+        3 => [0.0, second, chroma],
+        4 => [second, 0.0, chroma],
+        _ => [chroma, 0.0, second],
+    }
+}
+
+// Hue in degrees (0 to 360), saturation and value (0 to 1) of an RGB color
+pub fn rgb_to_hsv(rgb: [f32; 3]) -> [f32; 3] {
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    let chroma = max - rgb[0].min(rgb[1]).min(rgb[2]);
+    let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+    [hue(rgb, max, chroma), saturation, max]
+}
+
+// RGB color of a hue in degrees, saturation and value
+pub fn hsv_to_rgb(hsv: [f32; 3]) -> [f32; 3] {
+    let [hue, saturation, value] = hsv;
+    let chroma = value * saturation;
+    let offset = value - chroma;
+    hue_chroma_to_rgb(hue, chroma).map(|channel| channel + offset)
+}
+
+// Hue in degrees (0 to 360), saturation and lightness (0 to 1) of an RGB color
+pub fn rgb_to_hsl(rgb: [f32; 3]) -> [f32; 3] {
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    let min = rgb[0].min(rgb[1]).min(rgb[2]);
+    let chroma = max - min;
+    let lightness = (max + min) / 2.0;
+    let saturation = if chroma == 0.0 { 0.0 } else { chroma / (1.0 - (2.0 * lightness - 1.0).abs()) };
+    [hue(rgb, max, chroma), saturation, lightness]
+}
+
+// RGB color of a hue in degrees, saturation and lightness
+pub fn hsl_to_rgb(hsl: [f32; 3]) -> [f32; 3] {
+    let [hue, saturation, lightness] = hsl;
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let offset = lightness - chroma / 2.0;
+    hue_chroma_to_rgb(hue, chroma).map(|channel| channel + offset)
+}
+
+// Undoes the sRGB transfer curve, giving light intensity
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    }
+    else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Applies the sRGB transfer curve to a light intensity
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    }
+    else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Linear light RGB of an sRGB color
+pub fn rgb_to_linear(rgb: [f32; 3]) -> [f32; 3] {
+    rgb.map(srgb_to_linear)
+}
+
+// sRGB color of linear light RGB
+pub fn linear_to_rgb(linear: [f32; 3]) -> [f32; 3] {
+    linear.map(linear_to_srgb)
+}
+
+// CIE XYZ of linear light RGB with sRGB primaries (D65 white, Y = 1 for white)
+pub fn linear_to_xyz(linear: [f32; 3]) -> [f32; 3] {
+    multiply(&[
+        [0.4124564, 0.3575761, 0.1804375],
+        [0.2126729, 0.7151522, 0.072175],
+        [0.0193339, 0.119192, 0.9503041],
+    ], linear)
+}
+
+// Linear light RGB with sRGB primaries of a CIE XYZ value (D65 white)
+pub fn xyz_to_linear(xyz: [f32; 3]) -> [f32; 3] {
+    multiply(&[
+        [3.2404542, -1.5371385, -0.4985314],
+        [-0.969266, 1.8760108, 0.041556],
+        [0.0556434, -0.2040259, 1.0572252],
+    ], xyz)
+}
+
+// CIE XYZ of an sRGB color (D65 white, Y = 1 for white)
+pub fn rgb_to_xyz(rgb: [f32; 3]) -> [f32; 3] {
+    linear_to_xyz(rgb_to_linear(rgb))
+}
+
+// sRGB color of a CIE XYZ value (D65 white), not clamped, so colors outside sRGB fall outside 0 to 1
+pub fn xyz_to_rgb(xyz: [f32; 3]) -> [f32; 3] {
+    linear_to_rgb(xyz_to_linear(xyz))
+}
+
+// The XYZ value that looks the same under another white point (Bradford chromatic adaptation)
+pub fn adapt(xyz: [f32; 3], from_white: [f32; 3], to_white: [f32; 3]) -> [f32; 3] {
+    let (from, to) = (multiply(&BRADFORD, from_white), multiply(&BRADFORD, to_white));
+    let cone = multiply(&BRADFORD, xyz);
+    multiply(&BRADFORD_INVERSE, [0, 1, 2].map(|i| cone[i] * to[i] / from[i]))
+}
+
+// XYZ under D65 (as sRGB colors are) adapted to D50
+pub fn d65_to_d50(xyz: [f32; 3]) -> [f32; 3] {
+    adapt(xyz, D65, D50)
+}
+
+// Cube root with a linear segment near zero, as used by CIELAB
+fn lab_f(t: f32) -> f32 {
+    let delta: f32 = 6.0 / 29.0;
+    if t > delta.powi(3) { t.cbrt() } else { t / (3.0 * delta * delta) + 4.0 / 29.0 }
+}
+
+fn lab_f_inverse(t: f32) -> f32 {
+    let delta: f32 = 6.0 / 29.0;
+    if t > delta { t.powi(3) } else { 3.0 * delta * delta * (t - 4.0 / 29.0) }
+}
+
+// CIELAB of an XYZ value relative to a white point: L from 0 to 100, a and b roughly -128 to 127
+pub fn xyz_to_lab(xyz: [f32; 3], white: [f32; 3]) -> [f32; 3] {
+    let [fx, fy, fz] = [lab_f(xyz[0] / white[0]), lab_f(xyz[1] / white[1]), lab_f(xyz[2] / white[2])];
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// XYZ value of a CIELAB color relative to a white point
+pub fn lab_to_xyz(lab: [f32; 3], white: [f32; 3]) -> [f32; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let fx = fy + lab[1] / 500.0;
+    let fz = fy - lab[2] / 200.0;
+    [white[0] * lab_f_inverse(fx), white[1] * lab_f_inverse(fy), white[2] * lab_f_inverse(fz)]
+}
+
+// CIELAB (D65) of an sRGB color
+pub fn rgb_to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    xyz_to_lab(rgb_to_xyz(rgb), D65)
+}
+
+// sRGB color of a CIELAB (D65) color
+pub fn lab_to_rgb(lab: [f32; 3]) -> [f32; 3] {
+    xyz_to_rgb(lab_to_xyz(lab, D65))
+}
+
+// CIELAB (D50, as used by ICC profiles and print) of an sRGB color, adapted with Bradford
+pub fn rgb_to_lab_d50(rgb: [f32; 3]) -> [f32; 3] {
+    xyz_to_lab(d65_to_d50(rgb_to_xyz(rgb)), D50)
+}
+
+// Lightness, chroma and hue in degrees (0 to 360) of a CIELAB color, its polar form
+pub fn lab_to_lch(lab: [f32; 3]) -> [f32; 3] {
+    let [lightness, a, b] = lab;
+    [lightness, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0)]
+}
+
+// CIELAB color of a lightness, chroma and hue in degrees
+pub fn lch_to_lab(lch: [f32; 3]) -> [f32; 3] {
+    let [lightness, chroma, hue] = lch;
+    let (sin, cos) = hue.to_radians().sin_cos();
+    [lightness, chroma * cos, chroma * sin]
+}
+
+// LCh (D65) of an sRGB color
+pub fn rgb_to_lch(rgb: [f32; 3]) -> [f32; 3] {
+    lab_to_lch(rgb_to_lab(rgb))
+}
+
+// sRGB color of an LCh (D65) color
+pub fn lch_to_rgb(lch: [f32; 3]) -> [f32; 3] {
+    lab_to_rgb(lch_to_lab(lch))
+}
+
+// CIE 1976 color difference: the straight distance between two CIELAB colors
+// About 2.3 is the smallest difference most people notice
+pub fn delta_e_1976(first: [f32; 3], second: [f32; 3]) -> f32 {
+    (0..3).map(|i| (first[i] - second[i]).powi(2)).sum::<f32>().sqrt()
+}
+
+// CIEDE2000 color difference between two CIELAB colors, which corrects the 1976 formula for how
+// differently people see changes in lightness, chroma and hue (following Sharma, Wu and Dalal 2005)
+pub fn delta_e_2000(first: [f32; 3], second: [f32; 3]) -> f32 {
+    // Worked in f64, the formula subtracts nearly equal angles
+    let [l1, a1, b1] = first.map(|value| value as f64);
+    let [l2, a2, b2] = second.map(|value| value as f64);
+
+    // Stretch a to even out chroma for near-neutral colors
+    let mean_chroma = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (mean_chroma.powi(7) / (mean_chroma.powi(7) + 25f64.powi(7))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |a: f64, b: f64| if a == 0.0 && b == 0.0 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    // Differences in lightness, chroma and hue
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    }
+    else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    }
+    else if h2 <= h1 {
+        h2 - h1 + 360.0
+    }
+    else {
+        h2 - h1 - 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    // Means, with the hue mean taken the short way around the circle
+    let mean_l = (l1 + l2) / 2.0;
+    let mean_c = (c1 + c2) / 2.0;
+    let mean_h = if c1 * c2 == 0.0 {
+        h1 + h2
+    }
+    else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    }
+    else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    }
+    else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    // Weights for each difference, and the rotation that fixes up blues
+    let t = 1.0 - 0.17 * (mean_h - 30.0).to_radians().cos() + 0.24 * (2.0 * mean_h).to_radians().cos()
+        + 0.32 * (3.0 * mean_h + 6.0).to_radians().cos() - 0.2 * (4.0 * mean_h - 63.0).to_radians().cos();
+    let weight_l = 1.0 + 0.015 * (mean_l - 50.0).powi(2) / (20.0 + (mean_l - 50.0).powi(2)).sqrt();
+    let weight_c = 1.0 + 0.045 * mean_c;
+    let weight_h = 1.0 + 0.015 * mean_c * t;
+    let rotation_angle = 30.0 * (-((mean_h - 275.0) / 25.0).powi(2)).exp();
+    let rotation = -2.0 * (mean_c.powi(7) / (mean_c.powi(7) + 25f64.powi(7))).sqrt() * (2.0 * rotation_angle).to_radians().sin();
+
+    let (l, c, h) = (delta_l / weight_l, delta_c / weight_c, delta_h / weight_h);
+    (l * l + c * c + h * h + rotation * c * h).sqrt() as f32
+}
+
+// Formulas for the perceived difference between two colors
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DeltaE {
+    Cie1976,
+    Ciede2000,
+}
+
+impl DeltaE {
+    // Formula named cie1976 (or 1976) or ciede2000 (or 2000), ignoring case
+    pub fn parse(name: &str) -> Result<DeltaE, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "cie1976" | "1976" => Ok(DeltaE::Cie1976),
+            "ciede2000" | "2000" => Ok(DeltaE::Ciede2000),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown color difference formula: {}", name))),
+        }
+    }
+
+    // Difference between two CIELAB colors
+    pub fn between(self, first: [f32; 3], second: [f32; 3]) -> f32 {
+        match self {
+            DeltaE::Cie1976 => delta_e_1976(first, second),
+            DeltaE::Ciede2000 => delta_e_2000(first, second),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: [f32; 3], b: [f32; 3], tolerance: f32) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() <= tolerance)
+    }
+
+    #[test]
+    fn test_hsv_round_trip() {
+        assert_eq!(rgb_to_hsv([1.0, 0.0, 0.0]), [0.0, 1.0, 1.0]);
+        assert!(close(rgb_to_hsv([0.0, 0.5, 0.5]), [180.0, 1.0, 0.5], 1e-5));
+        assert_eq!(rgb_to_hsv([0.3, 0.3, 0.3]), [0.0, 0.0, 0.3]);
+        for rgb in [[0.2, 0.4, 0.9], [0.9, 0.1, 0.5], [0.5, 0.8, 0.1], [0.0, 0.0, 0.0]] {
+            assert!(close(hsv_to_rgb(rgb_to_hsv(rgb)), rgb, 1e-5), "{:?}", rgb);
+        }
+    }
+
+    #[test]
+    fn test_hsl_round_trip() {
+        assert_eq!(rgb_to_hsl([1.0, 0.0, 0.0]), [0.0, 1.0, 0.5]);
+        assert!(close(rgb_to_hsl([0.25, 0.75, 0.75]), [180.0, 0.5, 0.5], 1e-5));
+        assert_eq!(rgb_to_hsl([1.0, 1.0, 1.0]), [0.0, 0.0, 1.0]);
+        for rgb in [[0.2, 0.4, 0.9], [0.9, 0.1, 0.5], [0.5, 0.8, 0.1], [0.0, 0.0, 0.0], [1.0, 1.0, 0.0]] {
+            assert!(close(hsl_to_rgb(rgb_to_hsl(rgb)), rgb, 1e-5), "{:?}", rgb);
+        }
+    }
+
+    #[test]
+    fn test_bradford_and_lch() {
+        // D65 white becomes D50 white, and other colors match the published D65 to D50 matrix
+        assert!(close(d65_to_d50(D65), D50, 1e-5));
+        let adapted = d65_to_d50([0.3, 0.5, 0.2]);
+        let expected = [
+            1.0478112 * 0.3 + 0.0228866 * 0.5 - 0.050127 * 0.2,
+            0.0295424 * 0.3 + 0.9904844 * 0.5 - 0.0170491 * 0.2,
+            -0.0092345 * 0.3 + 0.0150436 * 0.5 + 0.7521316 * 0.2,
+        ];
+        assert!(close(adapted, expected, 1e-4));
+        assert!(close(adapt(adapted, D50, D65), [0.3, 0.5, 0.2], 1e-5));
+        assert!(close(rgb_to_lab_d50([1.0, 1.0, 1.0]), [100.0, 0.0, 0.0], 0.01));
+        assert!(close(xyz_to_rgb(adapt(lab_to_xyz(rgb_to_lab_d50([0.2, 0.6, 0.4]), D50), D50, D65)), [0.2, 0.6, 0.4], 1e-4));
+
+        assert!(close(lab_to_lch([50.0, 0.0, -20.0]), [50.0, 20.0, 270.0], 1e-4));
+        for rgb in [[0.2, 0.4, 0.9], [0.9, 0.1, 0.5]] {
+            assert!(close(lch_to_rgb(rgb_to_lch(rgb)), rgb, 1e-4), "{:?}", rgb);
+        }
+    }
+
+    #[test]
+    fn test_delta_e() {
+        assert_eq!(delta_e_1976([50.0, 3.0, 0.0], [50.0, 0.0, 4.0]), 5.0);
+
+        // Reference pairs from Sharma, Wu and Dalal's CIEDE2000 test data
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+            ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0011], 7.2195),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            ([2.0776, 0.0795, -1.135], [0.9033, -0.0636, -0.5514], 0.9082),
+        ];
+        for (first, second, expected) in pairs {
+            assert!((delta_e_2000(first, second) - expected).abs() < 1e-3, "{:?} {:?}", first, second);
+            assert!((delta_e_2000(second, first) - expected).abs() < 1e-3);
+        }
+
+        assert_eq!(DeltaE::parse("CIEDE2000").unwrap().between([50.0, 2.5, 0.0], [50.0, 2.5, 0.0]), 0.0);
+        assert!(DeltaE::parse("cmc").is_err());
+    }
+
+    #[test]
+    fn test_lab_reference_values() {
+        // White, black and pure sRGB red have well known CIELAB values
+        assert!(close(rgb_to_lab([1.0, 1.0, 1.0]), [100.0, 0.0, 0.0], 0.01));
+        assert!(close(rgb_to_lab([0.0, 0.0, 0.0]), [0.0, 0.0, 0.0], 0.01));
+        assert!(close(rgb_to_lab([1.0, 0.0, 0.0]), [53.24, 80.09, 67.20], 0.05));
+        for rgb in [[0.2, 0.4, 0.9], [0.9, 0.1, 0.5], [0.01, 0.02, 0.0]] {
+            assert!(close(lab_to_rgb(rgb_to_lab(rgb)), rgb, 1e-4), "{:?}", rgb);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+// Grid composer for contact sheets: any number of images laid out in rows and columns
+
+use crate::canvas::{self, Anchor};
+use crate::font;
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::resize::{self, Filter, Size};
+use crate::{Data, Header};
+
+// Settings for laying out images in a grid
+// Images fill the grid row by row, starting at the top-left cell
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GridLayout<P: Pixel> {
+    // Number of cells in each row, the number of rows follows from the number of images
+    pub columns: usize,
+    // Gap between neighbouring cells
+    pub spacing: usize,
+    // Border around the whole grid
+    pub margin: usize,
+    // Color of the gaps, margins and any part of a cell not covered by its image
+    pub background: P,
+    // Where each image sits inside its cell
+    pub align: Anchor,
+    // Fixed size of every cell; when unset each column is as wide as its widest image
+    // and each row as tall as its tallest image
+    pub cell_size: Option<(usize, usize)>,
+    // Filter used to scale images to fit a fixed cell size; when unset images are cropped to the cell
+    pub scaling: Option<Filter>,
+    // Color and scale of the captions drawn under each cell
+    pub caption_color: P,
+    pub caption_scale: usize,
+}
+
+impl<P: Pixel> GridLayout<P> {
+    // Grid with the given number of columns, no spacing or margins, centered images and white captions
+    pub fn new(columns: usize, background: P) -> Self {
+        GridLayout {
+            columns,
+            spacing: 0,
+            margin: 0,
+            background,
+            align: Anchor::Center,
+            cell_size: None,
+            scaling: None,
+            caption_color: P::from_bgra([1.0; 4]),
+            caption_scale: 1,
+        }
+    }
+}
+
+// Longest start of the caption that fits in the given width
+fn fit_caption(caption: &str, width: usize, scale: usize) -> &str {
+    let mut end = 0;
+    for (index, character) in caption.char_indices() {
+        if font::text_width(&caption[..index + character.len_utf8()], scale) > width {
+            break;
+        }
+        end = index + character.len_utf8();
+    }
+    &caption[..end]
+}
+
+// Lays out images in a grid, assuming the first row of every image is the top
+// Captions are matched to images by position, missing or empty captions are skipped
+pub fn compose_grid_image<P: Pixel>(images: &[&Image<P>], captions: &[&str], layout: &GridLayout<P>) -> Image<P> {
+    let count = images.len();
+    let columns = layout.columns.clamp(1, count.max(1));
+    let rows = count.div_ceil(columns);
+
+    // Scale images to fit their cells when asked to
+    let scaled: Vec<Image<P>> = match (layout.cell_size, layout.scaling) {
+        (Some((cell_width, cell_height)), Some(filter)) => images.iter().map(|image| {
+            let (width, height) = resize::target_size(image.width(), image.height(), Size::Fit(cell_width, cell_height));
+            resize::resize_image(image, width, height, filter)
+        }).collect(),
+        _ => Vec::new(),
+    };
+    let cell_image = |index: usize| if scaled.is_empty() { images[index] } else { &scaled[index] };
+
+    // Width of every column and height of every row
+    let (column_widths, row_heights) = match layout.cell_size {
+        Some((cell_width, cell_height)) => (vec![cell_width; columns], vec![cell_height; rows]),
+        None => {
+            let mut column_widths = vec![0; columns];
+            let mut row_heights = vec![0; rows];
+            for (index, image) in images.iter().enumerate() {
+                column_widths[index % columns] = column_widths[index % columns].max(image.width());
+                row_heights[index / columns] = row_heights[index / columns].max(image.height());
+            }
+            (column_widths, row_heights)
+        }
+    };
+
+    // Room under each row for one line of caption text
+    let scale = layout.caption_scale.max(1);
+    let caption_height = if captions.iter().any(|caption| !caption.is_empty()) { font::text_height(scale) + 2 * scale } else { 0 };
+
+    let gaps = |cells: usize| layout.spacing * cells.saturating_sub(1);
+    let width = 2 * layout.margin + column_widths.iter().sum::<usize>() + gaps(columns);
+    let height = 2 * layout.margin + row_heights.iter().map(|row_height| row_height + caption_height).sum::<usize>() + gaps(rows);
+    let mut sheet = Image::new(width, height, layout.background);
+
+    // Left and top edge of every column and row
+    let mut column_starts = Vec::with_capacity(columns);
+    let mut x = layout.margin;
+    for column_width in &column_widths {
+        column_starts.push(x);
+        x += column_width + layout.spacing;
+    }
+    let mut row_starts = Vec::with_capacity(rows);
+    let mut y = layout.margin;
+    for row_height in &row_heights {
+        row_starts.push(y);
+        y += row_height + caption_height + layout.spacing;
+    }
+
+    for index in 0..count {
+        let (column, row) = (index % columns, index / columns);
+        let (x, y) = (column_starts[column], row_starts[row]);
+        let (cell_width, cell_height) = (column_widths[column], row_heights[row]);
+
+        // Align the image in its cell (cropping it if the cell is smaller)
+        let cell = canvas::resize_canvas_image(cell_image(index), cell_width, cell_height, layout.align, layout.background);
+        sheet.view_mut(x, y, cell_width, cell_height).copy_from(&cell.as_view());
+
+        // Caption centered under the cell
+        if let Some(caption) = captions.get(index) {
+            let caption = fit_caption(caption, cell_width, scale);
+            let caption_x = x + (cell_width - font::text_width(caption, scale)) / 2;
+            font::draw_text(&mut sheet, caption_x, y + cell_height + scale, caption, layout.caption_color, scale);
+        }
+    }
+
+    sheet
+}
+
+// Lays out images in a grid as they are displayed, producing a new 24-bit image stored bottom row first
+pub fn compose_grid<P: Pixel>(images: &[&Data<P>], captions: &[&str], layout: &GridLayout<P>) -> Data<P> {
+    // Work with every image top row first so the grid reads the same way it is displayed
+    let upright: Vec<Image<P>> = images.iter().map(|image| {
+        if image.header.is_top_down() { image.pixels.clone() } else { image.pixels.flip_vertical() }
+    }).collect();
+    let upright: Vec<&Image<P>> = upright.iter().collect();
+
+    let sheet = compose_grid_image(&upright, captions, layout).flip_vertical();
+    Data {header: Header::new(sheet.width(), sheet.height()), pixels: sheet}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    fn gray(value: u8) -> Gray<u8> {
+        Gray {value}
+    }
+
+    #[test]
+    fn test_varying_sizes_spacing_and_margin() {
+        let a = Image::new(2, 1, gray(1));
+        let b = Image::new(1, 2, gray(2));
+        let c = Image::new(1, 1, gray(3));
+        let mut layout = GridLayout::new(2, gray(0));
+        layout.spacing = 1;
+        layout.margin = 1;
+        layout.align = Anchor::TopLeft;
+        let sheet = compose_grid_image(&[&a, &b, &c], &[], &layout);
+
+        // Columns are 2 and 1 wide, rows are 2 and 1 tall
+        assert_eq!((sheet.width(), sheet.height()), (6, 6));
+        let values: Vec<u8> = sheet.data.iter().map(|pixel| pixel.value).collect();
+        assert_eq!(values, vec![
+            0, 0, 0, 0, 0, 0,
+            0, 1, 1, 0, 2, 0,
+            0, 0, 0, 0, 2, 0,
+            0, 0, 0, 0, 0, 0,
+            0, 3, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn test_fixed_cells_with_scaling() {
+        let wide = Image::new(8, 4, gray(200));
+        let tall = Image::new(2, 8, gray(100));
+        let mut layout = GridLayout::new(2, gray(0));
+        layout.cell_size = Some((4, 4));
+        layout.scaling = Some(Filter::Bilinear);
+        let sheet = compose_grid_image(&[&wide, &tall], &[], &layout);
+
+        // The wide image shrinks to 4x2 and the tall one to 1x4, both centered in their cells
+        assert_eq!((sheet.width(), sheet.height()), (8, 4));
+        assert_eq!(sheet.get(0, 0).value, 0);
+        assert_eq!(sheet.get(0, 1).value, 200);
+        assert_eq!(sheet.get(4, 0).value, 0);
+        assert_eq!(sheet.get(5, 0).value, 100);
+    }
+
+    #[test]
+    fn test_captions_add_room_under_cells() {
+        let image = Image::new(20, 10, gray(50));
+        let layout = GridLayout::new(1, gray(0));
+        let sheet = compose_grid_image(&[&image], &["AB"], &layout);
+        assert_eq!(sheet.height(), 10 + font::text_height(1) + 2);
+        assert!(sheet.data.iter().any(|pixel| pixel.value == 255));
+
+        // Captions longer than the cell are cut short
+        assert_eq!(fit_caption("ABCDEFGHIJ", 20, 1), "ABC");
+    }
+}
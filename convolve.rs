@@ -0,0 +1,260 @@
+// Convolution with arbitrary kernels, the basis of filters that look at neighbouring pixels
+// Kernels are laid over the image as written (top row of the kernel over the rows above),
+// the way image editors apply them, and run on every channel separately
+
+use std::io;
+
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::resample::{self, Edge};
+use crate::Data;
+
+// Grid of weights with an odd width and height, centered on the pixel being computed
+#[derive(Clone, PartialEq, Debug)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f32>,
+    // Row and column vectors whose product is the kernel, when there are any
+    factors: Option<(Vec<f32>, Vec<f32>)>,
+}
+
+impl Kernel {
+    // Kernel from weights in row-major order
+    // Separable kernels are detected here so they can take the faster two-pass route
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Kernel {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            panic!("Kernel width and height must be odd");
+        }
+        if weights.len() != width * height {
+            panic!("Kernel weights don't match kernel dimensions");
+        }
+        let factors = separate(width, height, &weights);
+        Kernel {width, height, weights, factors}
+    }
+
+    // Kernel from rows of comma-separated weights, top row first, such as ["0,-1,0", "-1,5,-1", "0,-1,0"]
+    pub fn parse<S: AsRef<str>>(rows: &[S]) -> Result<Kernel, io::Error> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let height = rows.len();
+        let width = rows.first().map_or(0, |row| row.as_ref().split(',').count());
+        if rows.iter().any(|row| row.as_ref().split(',').count() != width) {
+            return Err(invalid("Kernel rows must all have the same number of weights".to_string()));
+        }
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(invalid("Kernel width and height must be odd".to_string()));
+        }
+
+        let weights = rows.iter().flat_map(|row| row.as_ref().split(',')).map(|weight| {
+            weight.trim().parse::<f32>().map_err(|_| invalid(format!("Invalid kernel weight: {}", weight)))
+        }).collect::<Result<Vec<f32>, io::Error>>()?;
+        Ok(Kernel::new(width, height, weights))
+    }
+
+    // Kernel that is the product of a horizontal and a vertical vector
+    pub fn separable(horizontal: &[f32], vertical: &[f32]) -> Kernel {
+        let weights = vertical.iter().flat_map(|v| horizontal.iter().map(move |h| h * v)).collect();
+        let mut kernel = Kernel::new(horizontal.len(), vertical.len(), weights);
+        kernel.factors = Some((horizontal.to_vec(), vertical.to_vec()));
+        kernel
+    }
+
+    // Sum of all the weights
+    pub fn sum(&self) -> f32 {
+        self.weights.iter().sum()
+    }
+
+    // Horizontal and vertical vectors making up the kernel, if it is separable
+    pub fn factors(&self) -> Option<(&[f32], &[f32])> {
+        self.factors.as_ref().map(|(horizontal, vertical)| (horizontal.as_slice(), vertical.as_slice()))
+    }
+
+    // The same kernel upside down, used for images stored bottom row first
+    pub fn flipped_vertically(&self) -> Kernel {
+        let weights = self.weights.chunks(self.width).rev().flatten().copied().collect();
+        let factors = self.factors.as_ref().map(|(horizontal, vertical)| (horizontal.clone(), vertical.iter().rev().copied().collect()));
+        Kernel {width: self.width, height: self.height, weights, factors}
+    }
+}
+
+// Splits a kernel into a horizontal and a vertical vector when it is their product
+fn separate(width: usize, height: usize, weights: &[f32]) -> Option<(Vec<f32>, Vec<f32>)> {
+    // The largest weight gives the best conditioned row and column to divide by
+    let (largest, peak) = weights.iter().enumerate().max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+    if *peak == 0.0 || width * height == 1 {
+        return None;
+    }
+    let (peak_x, peak_y) = (largest % width, largest / width);
+    let horizontal: Vec<f32> = (0..width).map(|x| weights[peak_y * width + x] / peak).collect();
+    let vertical: Vec<f32> = (0..height).map(|y| weights[y * width + peak_x]).collect();
+
+    let tolerance = peak.abs() * 1e-5;
+    let matches = (0..height).all(|y| (0..width).all(|x| (weights[y * width + x] - horizontal[x] * vertical[y]).abs() <= tolerance));
+    if matches { Some((horizontal, vertical)) } else { None }
+}
+
+// How a convolution is applied
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Convolution {
+    // How pixels beyond the edges are read, Edge::Constant reads them as zero
+    pub edge: Edge,
+    // Divide by the sum of the weights (when it isn't zero) so overall brightness is kept
+    pub normalize: bool,
+    // Added to every result, as a fraction of full intensity (0.5 centers edge kernels on gray)
+    pub bias: f32,
+    // Whether alpha is convolved too, otherwise every pixel keeps its alpha
+    pub include_alpha: bool,
+}
+
+impl Convolution {
+    // Normalized convolution without bias that leaves alpha alone
+    pub fn new(edge: Edge) -> Self {
+        Convolution {edge, normalize: true, bias: 0.0, include_alpha: false}
+    }
+}
+
+// Source index for every position a kernel of the given radius can reach along one axis,
+// starting at -radius, None where the edge mode reads zero
+fn edge_indices(length: usize, radius: usize, edge: Edge) -> Vec<Option<usize>> {
+    (0..length + 2 * radius).map(|i| resample::edge_position(i as isize - radius as isize, length, edge)).collect()
+}
+
+// Correlates BGRA values with a kernel, keeping the same dimensions
+fn correlate(values: &[[f32; 4]], width: usize, height: usize, kernel_width: usize, kernel_height: usize, weights: &[f32], edge: Edge) -> Vec<[f32; 4]> {
+    let columns = edge_indices(width, kernel_width / 2, edge);
+    let rows = edge_indices(height, kernel_height / 2, edge);
+
+    let mut result = vec![[0.0f32; 4]; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let total = &mut result[y * width + x];
+            for (ky, row) in rows[y..y + kernel_height].iter().enumerate() {
+                let Some(row) = row else { continue };
+                for (kx, column) in columns[x..x + kernel_width].iter().enumerate() {
+                    let (Some(column), weight) = (column, weights[ky * kernel_width + kx]) else { continue };
+                    if weight == 0.0 {
+                        continue;
+                    }
+                    let value = values[row * width + column];
+                    for c in 0..4 {
+                        total[c] += value[c] * weight;
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+// Convolves normalized BGRA values, without the final scaling and bias
+// Separable kernels take two one-dimensional passes instead of one two-dimensional one
+pub fn convolve_values(values: &[[f32; 4]], width: usize, height: usize, kernel: &Kernel, edge: Edge) -> Vec<[f32; 4]> {
+    match kernel.factors() {
+        Some((horizontal, vertical)) => {
+            let rows = correlate(values, width, height, horizontal.len(), 1, horizontal, edge);
+            correlate(&rows, width, height, 1, vertical.len(), vertical, edge)
+        }
+        None => correlate(values, width, height, kernel.width, kernel.height, &kernel.weights, edge),
+    }
+}
+
+// Convolves every channel of an image, assuming the first row is the top
+// Results are rounded and clamped by the pixel's channel type; use f32 channels to keep
+// negative or out of range results, such as those of edge detection kernels
+pub fn convolve_image<P: Pixel>(image: &Image<P>, kernel: &Kernel, convolution: &Convolution) -> Image<P> {
+    let values: Vec<[f32; 4]> = image.data.iter().map(|pixel| pixel.to_bgra()).collect();
+    let convolved = convolve_values(&values, image.width(), image.height(), kernel, convolution.edge);
+
+    let sum = kernel.sum();
+    let scale = if convolution.normalize && sum != 0.0 { 1.0 / sum } else { 1.0 };
+    let data = convolved.iter().zip(values.iter()).map(|(result, original)| {
+        let mut bgra = result.map(|value| value * scale + convolution.bias);
+        if !convolution.include_alpha {
+            bgra[3] = original[3];
+        }
+        P::from_bgra(bgra)
+    }).collect();
+    Image::from_vec(image.width(), image.height(), data)
+}
+
+// Convolves an image with the kernel laid out as displayed
+// TGA files normally store the bottom row first, so the kernel is turned upside down for those images
+pub fn convolve<P: Pixel>(image: &Data<P>, kernel: &Kernel, convolution: &Convolution) -> Data<P> {
+    let pixels = if image.header.is_top_down() {
+        convolve_image(&image.pixels, kernel, convolution)
+    }
+    else {
+        convolve_image(&image.pixels, &kernel.flipped_vertically(), convolution)
+    };
+    image.with_pixels(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    fn values<P: Pixel>(image: &Image<P>) -> Vec<f32> {
+        image.data.iter().map(|pixel| pixel.to_bgra()[0] * 255.0).collect()
+    }
+
+    #[test]
+    fn test_separable_kernels_are_detected() {
+        let gaussian = Kernel::parse(&["1,2,1", "2,4,2", "1,2,1"]).unwrap();
+        let (horizontal, vertical) = gaussian.factors().unwrap();
+        assert_eq!(horizontal, &[0.5, 1.0, 0.5]);
+        assert_eq!(vertical, &[2.0, 4.0, 2.0]);
+        assert!(Kernel::parse(&["0,-1,0", "-1,5,-1", "0,-1,0"]).unwrap().factors().is_none());
+    }
+
+    #[test]
+    fn test_parse_checks_shape() {
+        assert_eq!(Kernel::parse(&["1,2,1"]).unwrap(), Kernel::new(3, 1, vec![1.0, 2.0, 1.0]));
+        assert!(Kernel::parse(&["1,2", "3,4"]).is_err());
+        assert!(Kernel::parse(&["1,2,1", "1,2"]).is_err());
+        assert!(Kernel::parse(&["1,x,1"]).is_err());
+        assert!(Kernel::parse::<&str>(&[]).is_err());
+    }
+
+    #[test]
+    fn test_separable_matches_full_kernel() {
+        let image = Image::from_fn(7, 5, |x, y| Gray {value: ((x * 37 + y * 91) % 256) as u8});
+        let values: Vec<[f32; 4]> = image.data.iter().map(|pixel| pixel.to_bgra()).collect();
+        let kernel = Kernel::separable(&[1.0, 3.0, 0.0, -2.0, 1.0], &[2.0, 1.0, -1.0]);
+        for edge in [Edge::Clamp, Edge::Mirror, Edge::Wrap, Edge::Constant] {
+            let fast = convolve_values(&values, 7, 5, &kernel, edge);
+            let full = correlate(&values, 7, 5, 5, 3, &kernel.weights, edge);
+            for (a, b) in fast.iter().zip(full.iter()) {
+                assert!((a[0] - b[0]).abs() < 1e-5, "{:?}", edge);
+            }
+        }
+    }
+
+    #[test]
+    fn test_edge_modes_and_normalizing() {
+        // Averaging three neighbours along a row: the edges differ by what lies beyond them
+        let image = Image::from_vec(3, 1, vec![Gray {value: 30u8}, Gray {value: 60}, Gray {value: 90}]);
+        let kernel = Kernel::parse(&["1,1,1"]).unwrap();
+        let averaged = |edge| values(&convolve_image(&image, &kernel, &Convolution::new(edge)));
+        assert_eq!(averaged(Edge::Clamp), vec![40.0, 60.0, 80.0]);
+        assert_eq!(averaged(Edge::Wrap), vec![60.0, 60.0, 60.0]);
+        assert_eq!(averaged(Edge::Mirror), vec![40.0, 60.0, 80.0]);
+        assert_eq!(averaged(Edge::Constant), vec![30.0, 60.0, 50.0]);
+    }
+
+    #[test]
+    fn test_float_channels_keep_signed_results() {
+        // A difference kernel on a ramp gives negative values, which f32 channels keep
+        let ramp = Image::from_fn(4, 1, |x, _| Gray {value: x as f32 * 0.1});
+        let difference = Kernel::parse(&["1,0,-1"]).unwrap();
+        let mut convolution = Convolution::new(Edge::Clamp);
+        convolution.normalize = false;
+        let result = convolve_image(&ramp, &difference, &convolution);
+        assert!((result.get(1, 0).value + 0.2).abs() < 1e-6);
+
+        // The same kernel with a bias on 8-bit channels is centered on gray
+        convolution.bias = 0.5;
+        let image = Image::from_fn(4, 1, |x, _| Gray {value: x as u8 * 10});
+        assert_eq!(convolve_image(&image, &difference, &convolution).get(1, 0).value, 108);
+    }
+}
@@ -0,0 +1,366 @@
+// Color grading with 1D and 3D lookup tables in the Adobe/Resolve .cube format
+// A .cube file is text: optional TITLE, LUT_1D_SIZE or LUT_3D_SIZE, optional DOMAIN_MIN and DOMAIN_MAX
+// (the input range, 0 to 1 by default), then one "red green blue" line per entry. 3D tables list red
+// fastest, then green, then blue. Lines starting with # are comments
+// Tables can also be baked from a chain of this project's adjustments and saved as .cube files
+
+use std::fs;
+use std::io;
+
+use crate::adjust::{ChannelLuts, Lut};
+use crate::hue::HueSaturation;
+use crate::image::Image;
+use crate::mixer::ChannelMixer;
+use crate::pixel::{ChannelMode, Pixel};
+use crate::swizzle::Swizzle;
+use crate::Data;
+
+// Largest sizes accepted, as in the .cube specification
+const MAX_1D_SIZE: usize = 65536;
+const MAX_3D_SIZE: usize = 256;
+
+// Ways of reading a 3D table between its entries
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CubeInterpolation {
+    // Blends the 8 corners of the surrounding cell
+    Trilinear,
+    // Blends the 4 corners of the tetrahedron holding the color, keeps grays on the gray axis
+    Tetrahedral,
+}
+
+// Table entries, as normalized [red, green, blue] outputs
+#[derive(Clone, PartialEq, Debug)]
+pub enum CubeTable {
+    // A curve for each channel, `size` entries long
+    OneD {size: usize, values: Vec<[f32; 3]>},
+    // A size x size x size grid of colors, red varying fastest
+    ThreeD {size: usize, values: Vec<[f32; 3]>},
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CubeLut {
+    pub title: Option<String>,
+    // Input values mapped to the first and last entries
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub table: CubeTable,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Three numbers following a keyword
+fn parse_triple(values: &[&str], line: usize) -> Result<[f32; 3], io::Error> {
+    let numbers = values.iter().map(|value| value.parse::<f32>()).collect::<Result<Vec<f32>, _>>();
+    match numbers.as_deref() {
+        Ok([red, green, blue]) => Ok([*red, *green, *blue]),
+        _ => Err(invalid_data(&format!("Line {}: expected three numbers", line))),
+    }
+}
+
+// Size following a LUT_1D_SIZE or LUT_3D_SIZE keyword
+fn parse_size(values: &[&str], max: usize, line: usize) -> Result<usize, io::Error> {
+    match values {
+        [size] => match size.parse::<usize>() {
+            Ok(size) if (2..=max).contains(&size) => Ok(size),
+            _ => Err(invalid_data(&format!("Line {}: LUT size must be from 2 to {}", line, max))),
+        },
+        _ => Err(invalid_data(&format!("Line {}: expected one size", line))),
+    }
+}
+
+// Entry position and the fraction of the way to the next entry for a normalized input
+fn locate(value: f32, size: usize) -> (usize, f32) {
+    let position = value.clamp(0.0, 1.0) * (size - 1) as f32;
+    let index = (position as usize).min(size - 2);
+    (index, position - index as f32)
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * t)
+}
+
+impl CubeLut {
+    // Table with `size` entries per channel sampled from a function of normalized RGB,
+    // over the default domain of 0 to 1
+    pub fn from_fn(size: usize, f: impl Fn([f32; 3]) -> [f32; 3]) -> CubeLut {
+        if !(2..=MAX_3D_SIZE).contains(&size) {
+            panic!("LUT size must be from 2 to {}", MAX_3D_SIZE);
+        }
+        let step = (size - 1) as f32;
+        let values = (0..size * size * size).map(|i| {
+            f([(i % size) as f32 / step, (i / size % size) as f32 / step, (i / (size * size)) as f32 / step])
+        }).collect();
+        CubeLut {title: None, domain_min: [0.0; 3], domain_max: [1.0; 3], table: CubeTable::ThreeD {size, values}}
+    }
+
+    // Table from the text of a .cube file
+    pub fn parse(text: &str) -> Result<CubeLut, io::Error> {
+        let mut title = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut size_1d = None;
+        let mut size_3d = None;
+        let mut values = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let number = number + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[0] {
+                "TITLE" => title = Some(line["TITLE".len()..].trim().trim_matches('"').to_string()),
+                "LUT_1D_SIZE" => size_1d = Some(parse_size(&words[1..], MAX_1D_SIZE, number)?),
+                "LUT_3D_SIZE" => size_3d = Some(parse_size(&words[1..], MAX_3D_SIZE, number)?),
+                "DOMAIN_MIN" => domain_min = parse_triple(&words[1..], number)?,
+                "DOMAIN_MAX" => domain_max = parse_triple(&words[1..], number)?,
+                // Resolve writes the domain as one range shared by every channel
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => match words[1..] {
+                    [min, max] => match (min.parse::<f32>(), max.parse::<f32>()) {
+                        (Ok(min), Ok(max)) => (domain_min, domain_max) = ([min; 3], [max; 3]),
+                        _ => return Err(invalid_data(&format!("Line {}: expected two numbers", number))),
+                    },
+                    _ => return Err(invalid_data(&format!("Line {}: expected two numbers", number))),
+                },
+                word if word.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    return Err(invalid_data(&format!("Line {}: unknown keyword {}", number, word)));
+                }
+                _ => values.push(parse_triple(&words, number)?),
+            }
+        }
+
+        if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+            return Err(invalid_data("LUT domain maximum must be above its minimum"));
+        }
+        let (table, expected) = match (size_1d, size_3d) {
+            (Some(size), None) => (CubeTable::OneD {size, values}, size),
+            (None, Some(size)) => (CubeTable::ThreeD {size, values}, size * size * size),
+            (None, None) => return Err(invalid_data("LUT has no LUT_1D_SIZE or LUT_3D_SIZE")),
+            (Some(_), Some(_)) => return Err(invalid_data("LUTs with both a 1D and a 3D table aren't supported")),
+        };
+        let (CubeTable::OneD {values, ..} | CubeTable::ThreeD {values, ..}) = &table;
+        if values.len() != expected {
+            return Err(invalid_data(&format!("LUT should have {} entries, found {}", expected, values.len())));
+        }
+        Ok(CubeLut {title, domain_min, domain_max, table})
+    }
+
+    // Table from a .cube file
+    pub fn load(file_path: &str) -> Result<CubeLut, io::Error> {
+        CubeLut::parse(&fs::read_to_string(file_path)?)
+    }
+
+    // The table as the text of a .cube file
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(title) = &self.title {
+            text += &format!("TITLE \"{}\"\n", title);
+        }
+        let values = match &self.table {
+            CubeTable::OneD {size, values} => {
+                text += &format!("LUT_1D_SIZE {}\n", size);
+                values
+            }
+            CubeTable::ThreeD {size, values} => {
+                text += &format!("LUT_3D_SIZE {}\n", size);
+                values
+            }
+        };
+        if self.domain_min != [0.0; 3] || self.domain_max != [1.0; 3] {
+            text += &format!("DOMAIN_MIN {} {} {}\n", self.domain_min[0], self.domain_min[1], self.domain_min[2]);
+            text += &format!("DOMAIN_MAX {} {} {}\n", self.domain_max[0], self.domain_max[1], self.domain_max[2]);
+        }
+        for [red, green, blue] in values {
+            text += &format!("{:.6} {:.6} {:.6}\n", red, green, blue);
+        }
+        text
+    }
+
+    // Writes the table as a .cube file
+    pub fn save(&self, file_path: &str) -> Result<(), io::Error> {
+        fs::write(file_path, self.to_text())
+    }
+
+    // Graded color for a normalized RGB color; 1D tables always interpolate linearly
+    pub fn apply(&self, rgb: [f32; 3], interpolation: CubeInterpolation) -> [f32; 3] {
+        // Position of each channel within the domain, 0 to 1
+        let input: [f32; 3] = [0, 1, 2].map(|c| (rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c]));
+        match &self.table {
+            CubeTable::OneD {size, values} => [0, 1, 2].map(|c| {
+                let (index, fraction) = locate(input[c], *size);
+                values[index][c] + (values[index + 1][c] - values[index][c]) * fraction
+            }),
+            CubeTable::ThreeD {size, values} => {
+                let size = *size;
+                let ([r, g, b], [fr, fg, fb]) = {
+                    let located = input.map(|value| locate(value, size));
+                    (located.map(|(index, _)| index), located.map(|(_, fraction)| fraction))
+                };
+                // Entry at an offset (0 or 1 along each axis) from the cell's first corner
+                let corner = |dr: usize, dg: usize, db: usize| values[(r + dr) + (g + dg) * size + (b + db) * size * size];
+
+                match interpolation {
+                    CubeInterpolation::Trilinear => {
+                        let front = lerp(lerp(corner(0, 0, 0), corner(1, 0, 0), fr), lerp(corner(0, 1, 0), corner(1, 1, 0), fr), fg);
+                        let back = lerp(lerp(corner(0, 0, 1), corner(1, 0, 1), fr), lerp(corner(0, 1, 1), corner(1, 1, 1), fr), fg);
+                        lerp(front, back, fb)
+                    }
+                    CubeInterpolation::Tetrahedral => {
+                        // The cell splits into six tetrahedra along its diagonal from (0,0,0) to (1,1,1),
+                        // picked by the order of the fractions; each is walked one axis at a time
+                        let (first, second) = if fr >= fg && fg >= fb {
+                            ((1, 0, 0), (1, 1, 0))
+                        }
+                        else if fr >= fb && fb >= fg {
+                            ((1, 0, 0), (1, 0, 1))
+                        }
+                        else if fb >= fr && fr >= fg {
+                            ((0, 0, 1), (1, 0, 1))
+                        }
+                        else if fg >= fr && fr >= fb {
+                            ((0, 1, 0), (1, 1, 0))
+                        }
+                        else if fg >= fb && fb >= fr {
+                            ((0, 1, 0), (0, 1, 1))
+                        }
+                        else {
+                            ((0, 0, 1), (0, 1, 1))
+                        };
+                        let mut sorted = [fr, fg, fb];
+                        sorted.sort_by(|a, b| b.total_cmp(a));
+                        let [largest, middle, smallest] = sorted;
+
+                        let (c0, c1, c2, c3) = (corner(0, 0, 0), corner(first.0, first.1, first.2), corner(second.0, second.1, second.2), corner(1, 1, 1));
+                        [0, 1, 2].map(|c| {
+                            (1.0 - largest) * c0[c] + (largest - middle) * c1[c] + (middle - smallest) * c2[c] + smallest * c3[c]
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
+
+// One step of a grade that a 3D table can be baked from
+#[derive(Clone, PartialEq, Debug)]
+pub enum GradeStep {
+    // A tonal table from adjust, on every channel or the luminance (tables are boxed to keep steps small)
+    Lut(Box<Lut>, ChannelMode),
+    // Per-channel tables, such as levels and curves
+    ChannelLuts(Box<ChannelLuts>),
+    HueSaturation(HueSaturation),
+    Mixer(ChannelMixer),
+    Swizzle(Swizzle),
+}
+
+impl GradeStep {
+    // Applies the step to normalized BGRA values
+    pub fn apply(&self, bgra: [f32; 4]) -> [f32; 4] {
+        match self {
+            GradeStep::Lut(lut, mode) => lut.apply(bgra, *mode),
+            GradeStep::ChannelLuts(luts) => luts.apply(bgra),
+            GradeStep::HueSaturation(adjustment) => adjustment.apply(bgra),
+            GradeStep::Mixer(mixer) => mixer.apply(bgra),
+            GradeStep::Swizzle(swizzle) => swizzle.apply(bgra),
+        }
+    }
+}
+
+// 3D table of a chain of steps applied in order, with `size` entries per channel
+// Steps that use alpha see opaque colors, and results are clamped to 0 to 1 between steps as they
+// would be in an 8-bit image
+pub fn bake(steps: &[GradeStep], size: usize) -> CubeLut {
+    CubeLut::from_fn(size, |[red, green, blue]| {
+        let bgra = steps.iter().fold([blue, green, red, 1.0], |bgra, step| step.apply(bgra).map(|value| value.clamp(0.0, 1.0)));
+        [bgra[2], bgra[1], bgra[0]]
+    })
+}
+
+// Grades every pixel with a table, keeping alpha
+pub fn apply_cube_image<P: Pixel>(image: &Image<P>, lut: &CubeLut, interpolation: CubeInterpolation) -> Image<P> {
+    image.map(|pixel| {
+        let bgra = pixel.to_bgra();
+        let [red, green, blue] = lut.apply([bgra[2], bgra[1], bgra[0]], interpolation);
+        P::from_bgra([blue, green, red, bgra[3]])
+    })
+}
+
+// Grades an image with a table
+pub fn apply_cube<P: Pixel>(image: &Data<P>, lut: &CubeLut, interpolation: CubeInterpolation) -> Data<P> {
+    image.with_pixels(apply_cube_image(&image.pixels, lut, interpolation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adjust::Adjustment;
+
+    fn close(a: [f32; 3], b: [f32; 3], tolerance: f32) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() <= tolerance)
+    }
+
+    #[test]
+    fn test_parse_and_export() {
+        let text = "# Swaps red and blue
+TITLE \"Swap\"
+LUT_3D_SIZE 2
+DOMAIN_MIN 0 0 0
+DOMAIN_MAX 1 1 2
+
+0 0 0
+0 0 1
+0 1 0
+0 1 1
+1 0 0
+1 0 1
+1 1 0
+1 1 1
+";
+        let lut = CubeLut::parse(text).unwrap();
+        assert_eq!(lut.title.as_deref(), Some("Swap"));
+        assert_eq!(lut.domain_max, [1.0, 1.0, 2.0]);
+        for interpolation in [CubeInterpolation::Trilinear, CubeInterpolation::Tetrahedral] {
+            // Blue is read over 0 to 2, so 1.0 is halfway
+            assert!(close(lut.apply([0.25, 0.5, 1.0], interpolation), [0.5, 0.5, 0.25], 1e-6));
+        }
+        assert_eq!(CubeLut::parse(&lut.to_text()).unwrap(), lut);
+
+        let one_d = CubeLut::parse("LUT_1D_SIZE 3\n0 0 1\n0.5 0.25 0.5\n1 1 0\n").unwrap();
+        assert!(close(one_d.apply([0.25, 0.5, 0.75], CubeInterpolation::Tetrahedral), [0.25, 0.25, 0.25], 1e-6));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(CubeLut::parse("LUT_3D_SIZE 2\n0 0 0\n").unwrap_err().to_string(), "LUT should have 8 entries, found 1");
+        assert_eq!(CubeLut::parse("0 0 0\n").unwrap_err().to_string(), "LUT has no LUT_1D_SIZE or LUT_3D_SIZE");
+        assert_eq!(CubeLut::parse("LUT_3D_SIZE 1\n").unwrap_err().to_string(), "Line 1: LUT size must be from 2 to 256");
+        assert_eq!(CubeLut::parse("LUT_1D_SIZE 2\n0 0\n1 1 1").unwrap_err().to_string(), "Line 2: expected three numbers");
+        assert!(CubeLut::parse("LUT_1D_SIZE 2\nSHAPER 1\n0 0 0\n1 1 1").is_err());
+        assert!(CubeLut::parse("LUT_1D_SIZE 2\nDOMAIN_MAX 0 1 1\n0 0 0\n1 1 1").is_err());
+    }
+
+    #[test]
+    fn test_interpolation() {
+        // Linear grades are reproduced exactly between entries by both methods
+        let mixer = ChannelMixer::from_rgb([[0.5, 0.25, 0.0, 0.1], [0.0, 0.5, 0.5, 0.0], [0.2, 0.2, 0.2, 0.2]]);
+        let lut = bake(&[GradeStep::Mixer(mixer)], 5);
+        for rgb in [[0.1, 0.7, 0.3], [0.9, 0.2, 0.55], [0.33, 0.33, 0.33]] {
+            let mixed = mixer.apply([rgb[2], rgb[1], rgb[0], 1.0]);
+            for interpolation in [CubeInterpolation::Trilinear, CubeInterpolation::Tetrahedral] {
+                assert!(close(lut.apply(rgb, interpolation), [mixed[2], mixed[1], mixed[0]], 1e-5));
+            }
+        }
+
+        // A baked tonal curve stays close to the original on any input
+        let gamma = Lut::from_adjustment(Adjustment::Gamma(2.0));
+        let lut = bake(&[GradeStep::Lut(Box::new(gamma.clone()), ChannelMode::PerChannel)], 33);
+        for rgb in [[0.1, 0.7, 0.3], [0.9, 0.2, 0.55]] {
+            let expected = rgb.map(|value| gamma.lookup(value));
+            assert!(close(lut.apply(rgb, CubeInterpolation::Tetrahedral), expected, 0.01));
+            assert!(close(lut.apply(rgb, CubeInterpolation::Trilinear), expected, 0.01));
+        }
+    }
+}
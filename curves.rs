@@ -0,0 +1,288 @@
+// Levels and curves, the Photoshop-style tone controls
+// Both bake down to per-channel lookup tables, and can be loaded from text files like this one:
+//
+//   # Values are 0 to 255, as in Photoshop
+//   levels rgb 10 240 1.2 0 255
+//   levels red 0 255 1.0 20 255
+//   curve rgb 0,0 64,50 192,210 255,255
+//
+// Each levels line gives the input black and white, the gamma, then the output black and white
+// (which may be left off); each curve line gives two or more input,output control points.
+// A line applies to rgb (the composite, every channel) or to red, green or blue, and lines take
+// effect in the order they are written
+
+use std::fs;
+use std::io;
+
+use crate::adjust::{ChannelLuts, Lut};
+
+// Which channels a levels or curve adjustment changes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    Composite,
+    Red,
+    Green,
+    Blue,
+}
+
+impl Target {
+    // Target named rgb (or composite), red, green or blue, ignoring case
+    pub fn parse(name: &str) -> Result<Target, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "rgb" | "composite" => Ok(Target::Composite),
+            "red" | "r" => Ok(Target::Red),
+            "green" | "g" => Ok(Target::Green),
+            "blue" | "b" => Ok(Target::Blue),
+            _ => Err(invalid_input(&format!("Unknown levels or curve channel: {}", name))),
+        }
+    }
+
+    // Per-channel tables applying a table to the targeted channels only
+    pub fn luts(self, lut: &Lut) -> ChannelLuts {
+        let identity = Lut::identity();
+        let pick = |targeted: bool| if targeted { lut.clone() } else { identity.clone() };
+        ChannelLuts {
+            red: pick(matches!(self, Target::Composite | Target::Red)),
+            green: pick(matches!(self, Target::Composite | Target::Green)),
+            blue: pick(matches!(self, Target::Composite | Target::Blue)),
+        }
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+// Levels on normalized values: the input range is stretched to 0 to 1, bent by the gamma and
+// squeezed into the output range
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Levels {
+    pub input_black: f32,
+    pub input_white: f32,
+    // Midtone gamma, above 1 brightens as in Photoshop
+    pub gamma: f32,
+    pub output_black: f32,
+    pub output_white: f32,
+}
+
+impl Levels {
+    // Leaves every value as it is
+    pub fn identity() -> Levels {
+        Levels {input_black: 0.0, input_white: 1.0, gamma: 1.0, output_black: 0.0, output_white: 1.0}
+    }
+
+    // Adjusts one normalized value
+    pub fn apply(&self, value: f32) -> f32 {
+        let range = (self.input_white - self.input_black).max(f32::EPSILON);
+        let stretched = ((value - self.input_black) / range).clamp(0.0, 1.0);
+        self.output_black + stretched.powf(1.0 / self.gamma) * (self.output_white - self.output_black)
+    }
+
+    // Table of these levels
+    pub fn lut(&self) -> Lut {
+        Lut::from_fn(|value| self.apply(value))
+    }
+}
+
+// Curve through control points, interpolated with a monotone cubic spline (Fritsch-Carlson) so it
+// never overshoots: between two points the curve stays between their outputs
+// Inputs before the first point or after the last get that point's output
+#[derive(Clone, PartialEq, Debug)]
+pub struct Curve {
+    points: Vec<(f32, f32)>,
+    // Slope of the curve at each point
+    tangents: Vec<f32>,
+}
+
+impl Curve {
+    // Curve through two or more normalized (input, output) points, given in any order
+    pub fn new(points: &[(f32, f32)]) -> Result<Curve, io::Error> {
+        if points.len() < 2 {
+            return Err(invalid_input("A curve needs at least two points"));
+        }
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if points.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(invalid_input("Curve points must have different inputs"));
+        }
+
+        // Slopes of the straight lines between neighbouring points
+        let secants: Vec<f32> = points.windows(2).map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0)).collect();
+
+        // Start from the average of the slopes on either side, flat at peaks and valleys
+        let last = secants.len() - 1;
+        let mut tangents: Vec<f32> = (0..points.len()).map(|i| match i {
+            0 => secants[0],
+            i if i > last => secants[last],
+            i if secants[i - 1] * secants[i] <= 0.0 => 0.0,
+            i => (secants[i - 1] + secants[i]) / 2.0,
+        }).collect();
+
+        // Then shrink tangents that would make a segment overshoot
+        for (i, secant) in secants.iter().enumerate() {
+            if *secant == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let (alpha, beta) = (tangents[i] / secant, tangents[i + 1] / secant);
+            let length = alpha.hypot(beta);
+            if length > 3.0 {
+                tangents[i] = 3.0 / length * alpha * secant;
+                tangents[i + 1] = 3.0 / length * beta * secant;
+            }
+        }
+        Ok(Curve {points, tangents})
+    }
+
+    // Output of the curve for a normalized input
+    pub fn apply(&self, value: f32) -> f32 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if value <= first.0 {
+            return first.1;
+        }
+        if value >= last.0 {
+            return last.1;
+        }
+
+        // Cubic Hermite interpolation over the segment holding the value
+        let i = self.points.windows(2).position(|pair| value < pair[1].0).unwrap_or(self.points.len() - 2);
+        let ((x0, y0), (x1, y1)) = (self.points[i], self.points[i + 1]);
+        let width = x1 - x0;
+        let t = (value - x0) / width;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * width * self.tangents[i]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * width * self.tangents[i + 1]
+    }
+
+    // Table of this curve
+    pub fn lut(&self) -> Lut {
+        Lut::from_fn(|value| self.apply(value))
+    }
+}
+
+// A number from 0 to 255 in a settings file, normalized
+fn parse_level(text: &str) -> Result<f32, io::Error> {
+    text.parse::<f32>().map(|value| value / 255.0).map_err(|_| invalid_input(&format!("Invalid number: {}", text)))
+}
+
+// Levels from the values of a levels line: input black, input white, gamma, and optionally output black and white
+fn parse_levels(values: &[&str]) -> Result<Levels, io::Error> {
+    let mut levels = Levels::identity();
+    match values {
+        [input_black, input_white, gamma, output @ ..] if output.is_empty() || output.len() == 2 => {
+            levels.input_black = parse_level(input_black)?;
+            levels.input_white = parse_level(input_white)?;
+            levels.gamma = gamma.parse().map_err(|_| invalid_input(&format!("Invalid gamma: {}", gamma)))?;
+            if let [output_black, output_white] = output {
+                levels.output_black = parse_level(output_black)?;
+                levels.output_white = parse_level(output_white)?;
+            }
+        }
+        _ => return Err(invalid_input("Levels need input black, input white, gamma and optionally output black and white")),
+    }
+    if levels.gamma <= 0.0 {
+        return Err(invalid_input("Gamma must be greater than zero"));
+    }
+    Ok(levels)
+}
+
+// Curve from the input,output pairs of a curve line
+fn parse_curve(values: &[&str]) -> Result<Curve, io::Error> {
+    let points = values.iter().map(|point| {
+        let (input, output) = point.split_once(',').ok_or_else(|| invalid_input(&format!("Invalid curve point: {}", point)))?;
+        Ok((parse_level(input)?, parse_level(output)?))
+    }).collect::<Result<Vec<(f32, f32)>, io::Error>>()?;
+    Curve::new(&points)
+}
+
+// Per-channel tables for the levels and curves in a settings file's text
+// Blank lines and anything after a # are ignored
+pub fn parse_settings(text: &str) -> Result<ChannelLuts, io::Error> {
+    let mut luts = ChannelLuts::uniform(&Lut::identity());
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (kind, target, values) = match words.as_slice() {
+            [] => continue,
+            [kind, target, values @ ..] => (*kind, Target::parse(target), values),
+            _ => return Err(invalid_input(&format!("Line {}: expected levels or curve, a channel and values", number + 1))),
+        };
+
+        // Errors name the line they come from
+        let at_line = |error: io::Error| invalid_input(&format!("Line {}: {}", number + 1, error));
+        let target = target.map_err(at_line)?;
+        let lut = match kind.to_ascii_lowercase().as_str() {
+            "levels" => parse_levels(values).map_err(at_line)?.lut(),
+            "curve" => parse_curve(values).map_err(at_line)?.lut(),
+            _ => return Err(invalid_input(&format!("Line {}: unknown adjustment {}", number + 1, kind))),
+        };
+        luts = luts.then(&target.luts(&lut));
+    }
+    Ok(luts)
+}
+
+// Per-channel tables for the levels and curves in a settings file
+pub fn load_settings(file_path: &str) -> Result<ChannelLuts, io::Error> {
+    parse_settings(&fs::read_to_string(file_path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        let levels = Levels {input_black: 0.2, input_white: 0.6, gamma: 1.0, output_black: 0.1, output_white: 0.9};
+        assert_eq!(levels.apply(0.1), 0.1);
+        assert!((levels.apply(0.4) - 0.5).abs() < 1e-6);
+        assert_eq!(levels.apply(0.8), 0.9);
+
+        let brighter = Levels {gamma: 2.0, ..Levels::identity()};
+        assert!((brighter.apply(0.25) - 0.5).abs() < 1e-6);
+        assert_eq!(Levels::identity().lut(), Lut::identity());
+    }
+
+    #[test]
+    fn test_curve_passes_through_points_without_overshooting() {
+        let points = [(0.0, 0.0), (0.25, 0.1), (0.3, 0.8), (0.7, 0.85), (1.0, 1.0)];
+        let curve = Curve::new(&points).unwrap();
+        for (x, y) in points {
+            assert!((curve.apply(x) - y).abs() < 1e-6);
+        }
+
+        // Rising points give a curve that never falls, and never leaves the range of the points
+        let mut previous = 0.0;
+        for i in 0..=1000 {
+            let value = curve.apply(i as f32 / 1000.0);
+            assert!(value >= previous - 1e-6 && value <= 1.0);
+            previous = value;
+        }
+
+        // Two points make a straight line, flat beyond the ends
+        let line = Curve::new(&[(0.8, 1.0), (0.2, 0.0)]).unwrap();
+        assert!((line.apply(0.5) - 0.5).abs() < 1e-6);
+        assert_eq!(line.apply(0.1), 0.0);
+        assert!(Curve::new(&[(0.5, 0.5)]).is_err());
+        assert!(Curve::new(&[(0.5, 0.5), (0.5, 0.7)]).is_err());
+    }
+
+    #[test]
+    fn test_settings_file() {
+        let luts = parse_settings("
+            # Invert red, then brighten everything
+            curve red 0,255 255,0
+            levels rgb 0 128 1.0  # input white at half
+        ").unwrap();
+        let close = |a: [f32; 4], b: [f32; 4]| (0..4).all(|i| (a[i] - b[i]).abs() < 1e-5);
+        assert!(close(luts.apply([0.0, 64.0 / 255.0, 0.0, 0.5]), [0.0, 0.5, 1.0, 0.5]));
+        assert!(close(luts.apply([1.0, 128.0 / 255.0, 1.0, 1.0]), [1.0, 1.0, 0.0, 1.0]));
+
+        assert_eq!(parse_settings("levels rgb 0 255").unwrap_err().to_string(), "Line 1: Levels need input black, input white, gamma and optionally output black and white");
+        assert_eq!(parse_settings("\ncurve purple 0,0 1,1").unwrap_err().to_string(), "Line 2: Unknown levels or curve channel: purple");
+        assert!(parse_settings("hue rgb 1").is_err());
+        assert!(parse_settings("levels").is_err());
+    }
+}
@@ -0,0 +1,242 @@
+// Edge detection: gradient operators (Sobel, Prewitt, Scharr) and the Canny edge detector
+// Everything works on the luminance of the image
+
+use std::f32::consts::PI;
+use std::io;
+
+use crate::blur;
+use crate::convolve::{self, Convolution, Kernel};
+use crate::image::Image;
+use crate::pixel::{Gray, Pixel};
+use crate::resample::Edge;
+use crate::Data;
+
+// 3x3 operators estimating the horizontal and vertical rate of change
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    // Central difference smoothed with weights 1, 2, 1
+    Sobel,
+    // Central difference averaged with equal weights
+    Prewitt,
+    // Central difference smoothed with weights 3, 10, 3, the most even response across angles
+    Scharr,
+}
+
+impl Operator {
+    // Operator named sobel, prewitt or scharr, ignoring case
+    pub fn parse(name: &str) -> Result<Operator, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "sobel" => Ok(Operator::Sobel),
+            "prewitt" => Ok(Operator::Prewitt),
+            "scharr" => Ok(Operator::Scharr),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown edge operator: {}", name))),
+        }
+    }
+
+    // Kernels for the change towards the right and towards the bottom, scaled so a
+    // step from black to white has a gradient of one
+    fn kernels(self) -> (Kernel, Kernel) {
+        let smoothing = match self {
+            Operator::Sobel => [1.0, 2.0, 1.0],
+            Operator::Prewitt => [1.0, 1.0, 1.0],
+            Operator::Scharr => [3.0, 10.0, 3.0],
+        };
+        let total: f32 = smoothing.iter().sum();
+        let smoothing = smoothing.map(|weight| weight / total);
+        let difference = [-1.0, 0.0, 1.0];
+        (Kernel::separable(&difference, &smoothing), Kernel::separable(&smoothing, &difference))
+    }
+}
+
+// How a gradient magnitude is turned into pixels
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EdgeOutput {
+    // Brighter where the edge is stronger
+    Grayscale,
+    // White where the magnitude reaches the threshold (a fraction of full intensity), black elsewhere
+    Binary(f32),
+}
+
+// Luminance of every pixel as unclamped floats
+fn luminance<P: Pixel>(image: &Image<P>) -> Image<Gray<f32>> {
+    image.map(|pixel| Gray::from_bgra(pixel.to_bgra()))
+}
+
+// Change towards the right and towards the bottom at every pixel, assuming the first row is the top
+pub fn gradients<P: Pixel>(image: &Image<P>, operator: Operator, edge: Edge) -> (Image<Gray<f32>>, Image<Gray<f32>>) {
+    let (horizontal, vertical) = operator.kernels();
+    let mut convolution = Convolution::new(edge);
+    convolution.normalize = false;
+    let luminance = luminance(image);
+    (convolve::convolve_image(&luminance, &horizontal, &convolution), convolve::convolve_image(&luminance, &vertical, &convolution))
+}
+
+// Strength of the change at every pixel, from 0 for flat areas up to about 1.4 for the sharpest corners
+pub fn gradient_magnitude<P: Pixel>(image: &Image<P>, operator: Operator, edge: Edge) -> Image<Gray<f32>> {
+    let (gx, gy) = gradients(image, operator, edge);
+    gx.zip_map(&gy, |x, y| Gray {value: x.value.hypot(y.value)})
+}
+
+// Direction of increasing brightness at every pixel in radians, counterclockwise from the right
+// as displayed (y points down in the image), between -PI and PI
+pub fn gradient_direction<P: Pixel>(image: &Image<P>, operator: Operator, edge: Edge) -> Image<Gray<f32>> {
+    let (gx, gy) = gradients(image, operator, edge);
+    gx.zip_map(&gy, |x, y| Gray {value: (-y.value).atan2(x.value)})
+}
+
+// Edge strength as pixels, grayscale or thresholded to black and white
+pub fn edge_magnitude_image<P: Pixel, Q: Pixel>(image: &Image<P>, operator: Operator, output: EdgeOutput, edge: Edge) -> Image<Q> {
+    gradient_magnitude(image, operator, edge).map(|magnitude| {
+        let value = match output {
+            EdgeOutput::Grayscale => magnitude.value,
+            EdgeOutput::Binary(threshold) => if magnitude.value >= threshold { 1.0 } else { 0.0 },
+        };
+        Q::from_bgra([value, value, value, 1.0])
+    })
+}
+
+// Gradient direction as pixels, with -PI as black rising to PI as white
+pub fn edge_direction_image<P: Pixel, Q: Pixel>(image: &Image<P>, operator: Operator, edge: Edge) -> Image<Q> {
+    gradient_direction(image, operator, edge).map(|direction| {
+        let value = (direction.value + PI) / (2.0 * PI);
+        Q::from_bgra([value, value, value, 1.0])
+    })
+}
+
+// Neighbour offsets on either side of a pixel along its gradient, for non-maximum suppression
+fn across_edge(gx: f32, gy: f32) -> (isize, isize) {
+    let degrees = gy.atan2(gx).to_degrees().rem_euclid(180.0);
+    if !(22.5..157.5).contains(&degrees) {
+        (1, 0)
+    }
+    else if degrees < 67.5 {
+        (1, 1)
+    }
+    else if degrees < 112.5 {
+        (0, 1)
+    }
+    else {
+        (-1, 1)
+    }
+}
+
+// Canny edge detector, giving one pixel wide white edges on black
+// The luminance is smoothed with a Gaussian of the given sigma, the Sobel gradient thinned to its
+// ridges, and ridge pixels kept if they reach the high threshold or connect to one that does
+// through pixels above the low threshold (both thresholds are fractions of full intensity)
+pub fn canny_image<P: Pixel, Q: Pixel>(image: &Image<P>, sigma: f32, low: f32, high: f32) -> Image<Q> {
+    let (width, height) = (image.width(), image.height());
+    let smoothed = blur::gaussian_blur_image(&luminance(image), sigma, Edge::Clamp);
+    let (gx, gy) = gradients(&smoothed, Operator::Sobel, Edge::Clamp);
+    let magnitude = gx.zip_map(&gy, |x, y| Gray {value: x.value.hypot(y.value)});
+    let magnitude_at = |x: isize, y: isize| {
+        if x < 0 || y < 0 || x as usize >= width || y as usize >= height { 0.0 } else { magnitude.get(x as usize, y as usize).value }
+    };
+
+    // Non-maximum suppression: keep pixels that are the peak across the edge
+    let ridge: Vec<f32> = (0..width * height).map(|i| {
+        let (x, y) = (i % width, i / width);
+        let value = magnitude.get(x, y).value;
+        let (dx, dy) = across_edge(gx.get(x, y).value, gy.get(x, y).value);
+        let (x, y) = (x as isize, y as isize);
+        if value > magnitude_at(x - dx, y - dy) && value >= magnitude_at(x + dx, y + dy) { value } else { 0.0 }
+    }).collect();
+
+    // Hysteresis: grow edges out from the strong pixels through the weak ones
+    let mut edges = vec![false; width * height];
+    let mut stack: Vec<usize> = (0..width * height).filter(|&i| ridge[i] >= high && ridge[i] > 0.0).collect();
+    for &i in &stack {
+        edges[i] = true;
+    }
+    while let Some(i) = stack.pop() {
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height {
+                continue;
+            }
+            let neighbour = ny as usize * width + nx as usize;
+            if !edges[neighbour] && ridge[neighbour] >= low && ridge[neighbour] > 0.0 {
+                edges[neighbour] = true;
+                stack.push(neighbour);
+            }
+        }
+    }
+
+    let data = edges.iter().map(|&is_edge| {
+        let value = if is_edge { 1.0 } else { 0.0 };
+        Q::from_bgra([value, value, value, 1.0])
+    }).collect();
+    Image::from_vec(width, height, data)
+}
+
+// Edge strength of an image as a new image of any pixel layout
+pub fn edge_magnitude<P: Pixel, Q: Pixel>(image: &Data<P>, operator: Operator, output: EdgeOutput, edge: Edge) -> Data<Q> {
+    image.with_pixels(edge_magnitude_image(&image.pixels, operator, output, edge))
+}
+
+// Gradient direction of an image as displayed
+// TGA files normally store the bottom row first, so the image is flipped for the duration
+pub fn edge_direction<P: Pixel, Q: Pixel>(image: &Data<P>, operator: Operator, edge: Edge) -> Data<Q> {
+    if image.header.is_top_down() {
+        image.with_pixels(edge_direction_image(&image.pixels, operator, edge))
+    }
+    else {
+        image.with_pixels(edge_direction_image::<P, Q>(&image.pixels.flip_vertical(), operator, edge).flip_vertical())
+    }
+}
+
+// Canny edges of an image as a new image of any pixel layout
+pub fn canny<P: Pixel, Q: Pixel>(image: &Data<P>, sigma: f32, low: f32, high: f32) -> Data<Q> {
+    image.with_pixels(canny_image(&image.pixels, sigma, low, high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Dark square in the middle of a white image
+    fn square() -> Image<Gray<u8>> {
+        Image::from_fn(20, 20, |x, y| Gray {value: if (5..15).contains(&x) && (5..15).contains(&y) { 0 } else { 255 }})
+    }
+
+    #[test]
+    fn test_gradients_of_step() {
+        // Black to white from left to right
+        let image = Image::from_fn(6, 3, |x, _| Gray {value: if x < 3 { 0u8 } else { 255 }});
+        for operator in [Operator::Sobel, Operator::Prewitt, Operator::Scharr] {
+            let magnitude = gradient_magnitude(&image, operator, Edge::Clamp);
+            assert!((magnitude.get(2, 1).value - 1.0).abs() < 1e-5, "{:?}", operator);
+            assert_eq!(magnitude.get(0, 1).value, 0.0);
+        }
+
+        // Brightness rises to the right, then upwards for a step from the bottom
+        let direction = gradient_direction(&image, Operator::Sobel, Edge::Clamp);
+        assert!(direction.get(2, 1).value.abs() < 1e-5);
+        let rising = Image::from_fn(3, 6, |_, y| Gray {value: if y < 3 { 255u8 } else { 0 }});
+        let direction = gradient_direction(&rising, Operator::Sobel, Edge::Clamp);
+        assert!((direction.get(1, 2).value - PI / 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_binary_output() {
+        let edges: Image<Gray<u8>> = edge_magnitude_image(&square(), Operator::Sobel, EdgeOutput::Binary(0.5), Edge::Clamp);
+        assert!(edges.data.iter().all(|pixel| pixel.value == 0 || pixel.value == 255));
+        assert_eq!(edges.get(4, 10).value, 255);
+        assert_eq!(edges.get(10, 10).value, 0);
+        assert_eq!(edges.get(0, 0).value, 0);
+    }
+
+    #[test]
+    fn test_canny_outlines_square() {
+        let edges: Image<Gray<u8>> = canny_image(&square(), 1.0, 0.1, 0.3);
+
+        // Every row through the square crosses exactly two thin edges
+        for y in 7..13 {
+            let crossings = edges.rows().nth(y).unwrap().iter().filter(|pixel| pixel.value == 255).count();
+            assert_eq!(crossings, 2, "row {}", y);
+        }
+        assert_eq!(edges.get(10, 10).value, 0);
+        assert_eq!(edges.get(0, 0).value, 0);
+    }
+}
@@ -0,0 +1,203 @@
+// Lossless geometric transforms: flips, transposes and quarter-turn rotations
+
+use std::io;
+
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::Data;
+
+// Side length of the square tiles used when rows become columns
+// Copying tile by tile keeps both the source and destination rows in cache
+const TILE: usize = 64;
+
+// The seven ways an image can be flipped or rotated by multiples of 90 degrees, leaving out the identity
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Orientation {
+    FlipHorizontal,
+    FlipVertical,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Transpose,
+    Transverse,
+}
+
+impl Orientation {
+    // Transform named flip-horizontal, flip-vertical, rotate-90, rotate-180, rotate-270 (all clockwise),
+    // transpose or transverse, ignoring case
+    pub fn parse(name: &str) -> Result<Orientation, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "flip-horizontal" | "mirror" => Ok(Orientation::FlipHorizontal),
+            "flip-vertical" | "flip" => Ok(Orientation::FlipVertical),
+            "rotate-90" | "90" => Ok(Orientation::Rotate90),
+            "rotate-180" | "180" => Ok(Orientation::Rotate180),
+            "rotate-270" | "270" => Ok(Orientation::Rotate270),
+            "transpose" => Ok(Orientation::Transpose),
+            "transverse" => Ok(Orientation::Transverse),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown orientation: {}", name))),
+        }
+    }
+
+    // The same transform with the vertical axis reversed, used for images stored bottom row first
+    fn mirrored_vertically(self) -> Orientation {
+        match self {
+            Orientation::Rotate90 => Orientation::Rotate270,
+            Orientation::Rotate270 => Orientation::Rotate90,
+            Orientation::Transpose => Orientation::Transverse,
+            Orientation::Transverse => Orientation::Transpose,
+            other => other,
+        }
+    }
+}
+
+// Copies every pixel to a new position in an image with rows and columns swapped
+// `destination` maps a source (x, y) to its position in the new image
+fn swap_axes<P: Pixel>(image: &Image<P>, destination: impl Fn(usize, usize) -> (usize, usize)) -> Image<P> {
+    let width = image.width();
+    let height = image.height();
+    if width == 0 || height == 0 {
+        return Image::from_vec(height, width, Vec::new());
+    }
+
+    let mut data = image.data.clone();
+
+    // Walk the source in tiles so reads and writes stay close together
+    for tile_y in (0..height).step_by(TILE) {
+        for tile_x in (0..width).step_by(TILE) {
+            for y in tile_y..(tile_y + TILE).min(height) {
+                for x in tile_x..(tile_x + TILE).min(width) {
+                    let (new_x, new_y) = destination(x, y);
+                    data[new_y * height + new_x] = image.data[y * width + x];
+                }
+            }
+        }
+    }
+
+    Image::from_vec(height, width, data)
+}
+
+// Transforms on images whose first row is the top of the picture
+impl<P: Pixel> Image<P> {
+    // Flips the image upside down
+    pub fn flip_vertical(&self) -> Image<P> {
+        let mut flipped = self.clone();
+        flipped.as_view_mut().flip_vertical();
+        flipped
+    }
+
+    // Mirrors the image left to right
+    pub fn flip_horizontal(&self) -> Image<P> {
+        let mut flipped = self.clone();
+        flipped.as_view_mut().flip_horizontal();
+        flipped
+    }
+
+    // Rotates the image 180 degrees, which reverses the order of every pixel
+    pub fn rotate_180(&self) -> Image<P> {
+        let data = self.data.iter().rev().copied().collect();
+        Image::from_vec(self.width(), self.height(), data)
+    }
+
+    // Rotates the image 90 degrees clockwise
+    pub fn rotate_90(&self) -> Image<P> {
+        let height = self.height();
+        swap_axes(self, |x, y| (height - 1 - y, x))
+    }
+
+    // Rotates the image 270 degrees clockwise (90 degrees counterclockwise)
+    pub fn rotate_270(&self) -> Image<P> {
+        let width = self.width();
+        swap_axes(self, |x, y| (y, width - 1 - x))
+    }
+
+    // Mirrors the image across the diagonal from the top-left corner to the bottom-right corner
+    pub fn transpose(&self) -> Image<P> {
+        swap_axes(self, |x, y| (y, x))
+    }
+
+    // Mirrors the image across the diagonal from the top-right corner to the bottom-left corner
+    pub fn transverse(&self) -> Image<P> {
+        let width = self.width();
+        let height = self.height();
+        swap_axes(self, |x, y| (height - 1 - y, width - 1 - x))
+    }
+
+    // Applies one of the transforms above
+    pub fn reorient(&self, orientation: Orientation) -> Image<P> {
+        match orientation {
+            Orientation::FlipHorizontal => self.flip_horizontal(),
+            Orientation::FlipVertical => self.flip_vertical(),
+            Orientation::Rotate90 => self.rotate_90(),
+            Orientation::Rotate180 => self.rotate_180(),
+            Orientation::Rotate270 => self.rotate_270(),
+            Orientation::Transpose => self.transpose(),
+            Orientation::Transverse => self.transverse(),
+        }
+    }
+}
+
+// Flips or rotates an image as it is displayed, updating the header's width and height
+// TGA files normally store the bottom row first, so rotations are reversed for those images
+pub fn reorient<P: Pixel>(image: &Data<P>, orientation: Orientation) -> Data<P> {
+    let orientation = if image.header.is_top_down() { orientation } else { orientation.mirrored_vertically() };
+    image.with_pixels(image.pixels.reorient(orientation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    // 3x2 image:
+    // 1 2 3
+    // 4 5 6
+    fn small_image() -> Image<Gray<u8>> {
+        Image::from_fn(3, 2, |x, y| Gray {value: (y * 3 + x + 1) as u8})
+    }
+
+    // Dimensions and values of an image in row-major order
+    fn layout(image: &Image<Gray<u8>>) -> (usize, usize, Vec<u8>) {
+        (image.width(), image.height(), image.data.iter().map(|pixel| pixel.value).collect())
+    }
+
+    #[test]
+    fn test_flips_and_half_turn() {
+        let image = small_image();
+        assert_eq!(layout(&image.flip_vertical()), (3, 2, vec![4, 5, 6, 1, 2, 3]));
+        assert_eq!(layout(&image.flip_horizontal()), (3, 2, vec![3, 2, 1, 6, 5, 4]));
+        assert_eq!(layout(&image.rotate_180()), (3, 2, vec![6, 5, 4, 3, 2, 1]));
+    }
+
+    #[test]
+    fn test_quarter_turns_swap_dimensions() {
+        let image = small_image();
+        assert_eq!(layout(&image.rotate_90()), (2, 3, vec![4, 1, 5, 2, 6, 3]));
+        assert_eq!(layout(&image.rotate_270()), (2, 3, vec![3, 6, 2, 5, 1, 4]));
+        assert_eq!(layout(&image.transpose()), (2, 3, vec![1, 4, 2, 5, 3, 6]));
+        assert_eq!(layout(&image.transverse()), (2, 3, vec![6, 3, 5, 2, 4, 1]));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Orientation::parse("Rotate-90").unwrap(), Orientation::Rotate90);
+        assert_eq!(Orientation::parse("mirror").unwrap(), Orientation::FlipHorizontal);
+        assert!(Orientation::parse("rotate-45").is_err());
+    }
+
+    #[test]
+    fn test_tiled_rotation_of_large_image() {
+        // Larger than one tile in both directions, with partial tiles at the edges
+        let image = Image::from_fn(150, 70, |x, y| Gray {value: ((x * 7 + y * 13) % 256) as u8});
+        let rotated = image.rotate_90();
+        assert_eq!((rotated.width(), rotated.height()), (70, 150));
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                assert_eq!(rotated.get(image.height() - 1 - y, x), image.get(x, y));
+            }
+        }
+
+        // Four quarter turns give back the original
+        assert_eq!(rotated.rotate_90().rotate_90().rotate_90(), image);
+        assert_eq!(image.transpose().transpose(), image);
+    }
+}
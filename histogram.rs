@@ -0,0 +1,269 @@
+// Histograms of image channels, their summary statistics, and reports as text, CSV or an image
+// Values are counted in 256 bins, the 8-bit values TGA files store, so statistics are on the
+// 0 to 255 scale. Any channel split can read is supported, luma included
+
+use crate::channel::Channel;
+use crate::font;
+use crate::image::Image;
+use crate::pixel::{Bgr, Pixel};
+use crate::split::Component;
+use crate::{Data, Header};
+
+// Percentiles included in every summary
+pub const PERCENTILES: [f32; 6] = [1.0, 5.0, 25.0, 75.0, 95.0, 99.0];
+
+// Number of pixels at each 8-bit value of one channel
+#[derive(Clone, PartialEq, Debug)]
+pub struct Histogram {
+    pub component: Component,
+    pub counts: [u64; 256],
+}
+
+// Summary of a histogram, on the 0 to 255 scale
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stats {
+    pub count: u64,
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    pub median: u8,
+    // Population standard deviation
+    pub std_dev: f64,
+    // Value at each of PERCENTILES
+    pub percentiles: [u8; PERCENTILES.len()],
+}
+
+impl Histogram {
+    // Counts the values of one channel of every pixel
+    pub fn of<P: Pixel>(image: &Image<P>, component: Component) -> Histogram {
+        let mut counts = [0; 256];
+        for pixel in &image.data {
+            counts[u8::from_f32(component.read(pixel.to_bgra())) as usize] += 1;
+        }
+        Histogram {component, counts}
+    }
+
+    // Number of values counted
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    // Smallest value with at least `percent` percent of the values at or below it (nearest rank),
+    // None for an empty histogram
+    pub fn percentile(&self, percent: f32) -> Option<u8> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((percent.clamp(0.0, 100.0) as f64 / 100.0 * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.counts.iter().position(|count| {
+            seen += count;
+            seen >= rank
+        }).map(|value| value as u8)
+    }
+
+    // Summary statistics, None for an empty histogram
+    pub fn stats(&self) -> Option<Stats> {
+        let count = self.total();
+        if count == 0 {
+            return None;
+        }
+        let min = self.counts.iter().position(|&count| count > 0)? as u8;
+        let max = self.counts.iter().rposition(|&count| count > 0)? as u8;
+        let weighted = |f: &dyn Fn(f64) -> f64| self.counts.iter().enumerate().map(|(value, &count)| f(value as f64) * count as f64).sum::<f64>() / count as f64;
+        let mean = weighted(&|value| value);
+        let std_dev = weighted(&|value| (value - mean).powi(2)).sqrt();
+        Some(Stats {
+            count,
+            min,
+            max,
+            mean,
+            median: self.percentile(50.0)?,
+            std_dev,
+            percentiles: PERCENTILES.map(|percent| self.percentile(percent).unwrap_or(0)),
+        })
+    }
+}
+
+// Histograms of several channels of an image
+pub fn histograms<P: Pixel>(image: &Data<P>, components: &[Component]) -> Vec<Histogram> {
+    components.iter().map(|component| Histogram::of(&image.pixels, *component)).collect()
+}
+
+// Summary statistics as an aligned table, one channel per row
+pub fn stats_text(histograms: &[Histogram]) -> String {
+    let mut text = format!("{:<11}{:>9}{:>5}{:>5}{:>9}{:>8}{:>9}", "channel", "count", "min", "max", "mean", "median", "std-dev");
+    for percent in PERCENTILES {
+        text += &format!("{:>6}", format!("p{}", percent));
+    }
+    text.push('\n');
+
+    for histogram in histograms {
+        text += &format!("{:<11}", histogram.component.name());
+        match histogram.stats() {
+            Some(stats) => {
+                text += &format!("{:>9}{:>5}{:>5}{:>9.2}{:>8}{:>9.2}", stats.count, stats.min, stats.max, stats.mean, stats.median, stats.std_dev);
+                for value in stats.percentiles {
+                    text += &format!("{:>6}", value);
+                }
+            }
+            None => text += &format!("{:>9}", 0),
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// Summary statistics as CSV, one channel per row; empty histograms leave the statistics blank
+pub fn stats_csv(histograms: &[Histogram]) -> String {
+    let mut text = String::from("channel,count,min,max,mean,median,std_dev");
+    for percent in PERCENTILES {
+        text += &format!(",p{}", percent);
+    }
+    text.push('\n');
+
+    for histogram in histograms {
+        text += histogram.component.name();
+        match histogram.stats() {
+            Some(stats) => {
+                text += &format!(",{},{},{},{:.4},{},{:.4}", stats.count, stats.min, stats.max, stats.mean, stats.median, stats.std_dev);
+                for value in stats.percentiles {
+                    text += &format!(",{}", value);
+                }
+            }
+            None => text += &format!(",0{}", ",".repeat(5 + PERCENTILES.len())),
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// The counts as CSV: one row per value from 0 to 255, one column per channel
+pub fn counts_csv(histograms: &[Histogram]) -> String {
+    let mut text = String::from("value");
+    for histogram in histograms {
+        text += &format!(",{}", histogram.component.name());
+    }
+    text.push('\n');
+
+    for value in 0..256 {
+        text += &value.to_string();
+        for histogram in histograms {
+            text += &format!(",{}", histogram.counts[value]);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// Color the bars of a channel are drawn in
+fn bar_color(component: Component) -> Bgr {
+    match component {
+        Component::Red => Bgr {blue: 60, green: 60, red: 230},
+        Component::Green => Bgr {blue: 60, green: 200, red: 60},
+        Component::Blue => Bgr {blue: 230, green: 90, red: 50},
+        _ => Bgr {blue: 210, green: 210, red: 210},
+    }
+}
+
+// Space around and between the panels, in pixels
+const MARGIN: usize = 6;
+
+// Draws each histogram as a 256 pixel wide panel of bars, stacked top to bottom with a caption
+// giving the channel, mean and median; bars are scaled so the tallest fills the panel
+pub fn render_histograms_image(histograms: &[Histogram], panel_height: usize) -> Image<Bgr> {
+    let background = Bgr {blue: 24, green: 24, red: 24};
+    let panel_background = Bgr {blue: 48, green: 48, red: 48};
+    let caption_color = Bgr {blue: 235, green: 235, red: 235};
+    let caption_height = font::text_height(1) + MARGIN / 2;
+    let slot_height = caption_height + panel_height + MARGIN;
+    let mut image = Image::new(256 + 2 * MARGIN, MARGIN + histograms.len() * slot_height, background);
+
+    for (index, histogram) in histograms.iter().enumerate() {
+        let top = MARGIN + index * slot_height;
+        let caption = match histogram.stats() {
+            Some(stats) => format!("{}  mean {:.1}  median {}", histogram.component.name(), stats.mean, stats.median),
+            None => format!("{}  empty", histogram.component.name()),
+        };
+        font::draw_text(&mut image, MARGIN, top, &caption, caption_color, 1);
+
+        let panel_top = top + caption_height;
+        let tallest = histogram.counts.iter().copied().max().unwrap_or(0).max(1);
+        let color = bar_color(histogram.component);
+        for (value, &count) in histogram.counts.iter().enumerate() {
+            // Any count at all shows at least one pixel
+            let bar = (count as f64 / tallest as f64 * panel_height as f64).ceil() as usize;
+            for y in 0..panel_height {
+                let pixel = if panel_height - y <= bar { color } else { panel_background };
+                image.put(MARGIN + value, panel_top + y, pixel);
+            }
+        }
+    }
+    image
+}
+
+// Histograms drawn as a new 24-bit image stored bottom row first
+pub fn render_histograms(histograms: &[Histogram], panel_height: usize) -> Data<Bgr> {
+    let pixels = render_histograms_image(histograms, panel_height).flip_vertical();
+    Data {header: Header::new(pixels.width(), pixels.height()), pixels}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    #[test]
+    fn test_counts_and_stats() {
+        // Values 0, 10, 20, ..., 90, one pixel each
+        let image = Image::from_fn(10, 1, |x, _| Gray {value: (x * 10) as u8});
+        let histogram = Histogram::of(&image, Component::Luma);
+        assert_eq!(histogram.total(), 10);
+        assert_eq!(histogram.counts[30], 1);
+
+        let stats = histogram.stats().unwrap();
+        assert_eq!((stats.min, stats.max, stats.median), (0, 90, 40));
+        assert!((stats.mean - 45.0).abs() < 1e-9);
+        assert!((stats.std_dev - 825f64.sqrt()).abs() < 1e-9);
+        assert_eq!(stats.percentiles, [0, 0, 20, 70, 90, 90]);
+        assert_eq!(histogram.percentile(0.0), Some(0));
+        assert_eq!(histogram.percentile(100.0), Some(90));
+
+        assert_eq!(Histogram::of(&Image::<Gray>::new(0, 0, Gray {value: 0}), Component::Red).stats(), None);
+    }
+
+    #[test]
+    fn test_subtracting_an_image_from_itself_leaves_zeros() {
+        let image = Image::from_fn(8, 8, |x, y| Bgr {blue: (x * 30) as u8, green: (y * 30) as u8, red: 200});
+        let difference = crate::subtract_blend(&image, &image);
+        for component in [Component::Red, Component::Green, Component::Blue] {
+            let stats = Histogram::of(&difference, component).stats().unwrap();
+            assert_eq!((stats.max, stats.mean), (0, 0.0));
+        }
+    }
+
+    #[test]
+    fn test_reports() {
+        let image = Image::from_vec(2, 1, vec![Bgr {blue: 0u8, green: 0, red: 255}, Bgr {blue: 0, green: 0, red: 0}]);
+        let histograms = [Histogram::of(&image, Component::Red), Histogram::of(&image, Component::Blue)];
+
+        let csv = counts_csv(&histograms);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 257);
+        assert_eq!((lines[0], lines[1], lines[256]), ("value,red,blue", "0,1,2", "255,1,0"));
+
+        let stats = stats_csv(&histograms);
+        assert!(stats.starts_with("channel,count,min,max,mean,median,std_dev,p1,p5,p25,p75,p95,p99\n"));
+        assert!(stats.contains("\nred,2,0,255,127.5000,0,127.5000,0,0,0,255,255,255\n"));
+        assert!(stats_text(&histograms).lines().nth(1).unwrap().starts_with("red"));
+
+        // One panel per histogram, the full height of the red bars in the red panel at 0 and 255
+        let rendered = render_histograms_image(&histograms, 20);
+        assert_eq!(rendered.width(), 256 + 2 * MARGIN);
+        let panel_bottom = MARGIN + font::text_height(1) + MARGIN / 2 + 19;
+        assert_eq!(rendered.get(MARGIN, panel_bottom), bar_color(Component::Red));
+        assert_eq!(rendered.get(MARGIN + 255, panel_bottom - 19), bar_color(Component::Red));
+        assert_eq!(rendered.get(MARGIN + 100, panel_bottom), Bgr {blue: 48, green: 48, red: 48});
+    }
+}
@@ -0,0 +1,205 @@
+// Hue/saturation adjustments, worked out in the HSV or HSL color model
+// Adjustments can be limited to a range of hues with a feathered edge, like Photoshop's Hue/Saturation
+// with a color range picked, so only the reds of an image are changed for example
+
+use std::io;
+
+use crate::color;
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::Data;
+
+// Cylindrical color models: hue plus saturation and value or lightness
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HueModel {
+    Hsv,
+    Hsl,
+}
+
+impl HueModel {
+    // Model named hsv or hsl, ignoring case
+    pub fn parse(name: &str) -> Result<HueModel, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "hsv" => Ok(HueModel::Hsv),
+            "hsl" => Ok(HueModel::Hsl),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown color model: {}", name))),
+        }
+    }
+
+    // Hue in degrees and the other two components of an RGB color
+    pub fn components(self, rgb: [f32; 3]) -> [f32; 3] {
+        match self {
+            HueModel::Hsv => color::rgb_to_hsv(rgb),
+            HueModel::Hsl => color::rgb_to_hsl(rgb),
+        }
+    }
+
+    // RGB color of a hue in degrees and the other two components
+    pub fn rgb(self, components: [f32; 3]) -> [f32; 3] {
+        match self {
+            HueModel::Hsv => color::hsv_to_rgb(components),
+            HueModel::Hsl => color::hsl_to_rgb(components),
+        }
+    }
+}
+
+// Hues an adjustment applies to: fully within `width` degrees either side of the center, fading out
+// over the next `feather` degrees
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HueRange {
+    pub center: f32,
+    pub width: f32,
+    pub feather: f32,
+}
+
+impl HueRange {
+    // Photoshop's preset ranges: reds, yellows, greens, cyans, blues and magentas,
+    // each fully covering 15 degrees either side with a 30 degree falloff
+    pub fn named(name: &str) -> Result<HueRange, io::Error> {
+        let center = match name.to_ascii_lowercase().as_str() {
+            "reds" | "red" => 0.0,
+            "yellows" | "yellow" => 60.0,
+            "greens" | "green" => 120.0,
+            "cyans" | "cyan" => 180.0,
+            "blues" | "blue" => 240.0,
+            "magentas" | "magenta" => 300.0,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown hue range: {}", name))),
+        };
+        Ok(HueRange {center, width: 15.0, feather: 30.0})
+    }
+
+    // How strongly a hue in degrees is affected, from 0 to 1
+    pub fn weight(&self, hue: f32) -> f32 {
+        let difference = (hue - self.center).rem_euclid(360.0);
+        let distance = difference.min(360.0 - difference);
+        if distance <= self.width {
+            1.0
+        }
+        else if distance >= self.width + self.feather {
+            0.0
+        }
+        else {
+            // Smoothstep, so the edge of the range doesn't show as a line
+            let t = 1.0 - (distance - self.width) / self.feather;
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+// Hue rotation and saturation and lightness changes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HueSaturation {
+    // Degrees added to the hue
+    pub hue: f32,
+    // -1 removes all color, 1 doubles the saturation
+    pub saturation: f32,
+    // Change to the lightness (HSL) or value (HSV): -1 turns everything black, 1 turns everything
+    // white in HSL and brings every color to full brightness in HSV
+    pub lightness: f32,
+    // Hues affected, every hue if None; gray pixels have no hue and are left out of any range
+    pub range: Option<HueRange>,
+    // Model the saturation and lightness are measured in
+    pub model: HueModel,
+}
+
+impl HueSaturation {
+    // Leaves every pixel as it is
+    pub fn identity() -> HueSaturation {
+        HueSaturation {hue: 0.0, saturation: 0.0, lightness: 0.0, range: None, model: HueModel::Hsl}
+    }
+
+    // Adjusts one pixel's normalized BGRA values, keeping alpha
+    pub fn apply(&self, bgra: [f32; 4]) -> [f32; 4] {
+        let rgb = [bgra[2], bgra[1], bgra[0]];
+        let [hue, saturation, lightness] = self.model.components(rgb);
+        let weight = match self.range {
+            Some(_) if saturation == 0.0 => return bgra,
+            Some(range) => range.weight(hue),
+            None => 1.0,
+        };
+        if weight == 0.0 {
+            return bgra;
+        }
+
+        let saturation = (saturation * (1.0 + self.saturation)).clamp(0.0, 1.0);
+        let lightness = if self.lightness >= 0.0 {
+            lightness + (1.0 - lightness) * self.lightness
+        }
+        else {
+            lightness * (1.0 + self.lightness)
+        };
+        let adjusted = self.model.rgb([hue + self.hue, saturation, lightness]);
+
+        // Blend toward the adjusted color by how far inside the range the hue is
+        let [red, green, blue] = [0, 1, 2].map(|c| rgb[c] + (adjusted[c] - rgb[c]) * weight);
+        [blue, green, red, bgra[3]]
+    }
+}
+
+// Adjusts the hue, saturation and lightness of every pixel
+pub fn hue_saturation_image<P: Pixel>(image: &Image<P>, adjustment: &HueSaturation) -> Image<P> {
+    image.map(|pixel| P::from_bgra(adjustment.apply(pixel.to_bgra())))
+}
+
+// Adjusts the hue, saturation and lightness of an image
+pub fn hue_saturation<P: Pixel>(image: &Data<P>, adjustment: &HueSaturation) -> Data<P> {
+    image.with_pixels(hue_saturation_image(&image.pixels, adjustment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Bgr;
+
+    #[test]
+    fn test_conversions_round_trip() {
+        // Every color in a coarse grid of 8-bit values comes back exactly
+        let image = Image::from_fn(64, 64, |x, y| Bgr {blue: (x * 4) as u8, green: (y * 4) as u8, red: ((x * 7 + y * 13) % 256) as u8});
+        for model in [HueModel::Hsv, HueModel::Hsl] {
+            let round_trip = image.map(|pixel| {
+                let bgra = pixel.to_bgra();
+                let [red, green, blue] = model.rgb(model.components([bgra[2], bgra[1], bgra[0]]));
+                Bgr::from_bgra([blue, green, red, 1.0])
+            });
+            assert_eq!(round_trip, image);
+        }
+    }
+
+    #[test]
+    fn test_adjustments() {
+        let red = Bgr {blue: 0u8, green: 0, red: 255}.to_bgra();
+        let rotate = HueSaturation {hue: 120.0, ..HueSaturation::identity()};
+        assert_eq!(Bgr::<u8>::from_bgra(rotate.apply(red)), Bgr {blue: 0, green: 255, red: 0});
+
+        let desaturate = HueSaturation {saturation: -1.0, ..HueSaturation::identity()};
+        let gray: Bgr = Bgr::from_bgra(desaturate.apply(Bgr {blue: 40u8, green: 80, red: 200}.to_bgra()));
+        assert!(gray.blue == gray.green && gray.green == gray.red);
+
+        let lighten = HueSaturation {lightness: 1.0, ..HueSaturation::identity()};
+        assert_eq!(lighten.apply(red), [1.0; 4]);
+
+        // In HSV, lowering the value darkens without washing the color out
+        let darken = HueSaturation {lightness: -0.5, model: HueModel::Hsv, ..HueSaturation::identity()};
+        assert_eq!(Bgr::<u8>::from_bgra(darken.apply(red)), Bgr {blue: 0, green: 0, red: 128});
+        assert_eq!(HueModel::parse("HSV").unwrap(), HueModel::Hsv);
+        assert!(HueModel::parse("lab").is_err());
+        assert_eq!(HueSaturation::identity().apply(red), red);
+    }
+
+    #[test]
+    fn test_hue_range() {
+        let range = HueRange::named("reds").unwrap();
+        assert_eq!(range.weight(350.0), 1.0);
+        assert_eq!(range.weight(15.0), 1.0);
+        assert_eq!(range.weight(60.0), 0.0);
+        assert!((range.weight(30.0) - 0.5).abs() < 1e-6);
+
+        // Only the red pixel loses its color, blue and gray are left alone
+        let adjustment = HueSaturation {saturation: -1.0, range: Some(range), ..HueSaturation::identity()};
+        let image = Image::from_vec(3, 1, vec![Bgr {blue: 0u8, green: 0, red: 200}, Bgr {blue: 200, green: 0, red: 0}, Bgr {blue: 90, green: 90, red: 90}]);
+        let adjusted = hue_saturation_image(&image, &adjustment);
+        assert_eq!(adjusted.get(0, 0), Bgr {blue: 100, green: 100, red: 100});
+        assert_eq!(adjusted.get(1, 0), image.get(1, 0));
+        assert_eq!(adjusted.get(2, 0), image.get(2, 0));
+    }
+}
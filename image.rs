@@ -0,0 +1,120 @@
+// Image container holding pixels of any layout in row-major order
+
+use crate::pixel::Pixel;
+
+// Struct that represents pixel data with its dimensions
+// Rows are kept in the order they appear in the file (bottom row first for most TGA files)
+#[derive(PartialEq, Debug)]
+#[derive(Clone)]
+pub struct Image<P: Pixel> {
+    width: usize,
+    height: usize,
+    pub data: Vec<P>,
+}
+
+impl<P: Pixel> Image<P> {
+    // Creates an image filled with one pixel value
+    pub fn new(width: usize, height: usize, fill: P) -> Self {
+        Image {width, height, data: vec![fill; width * height]}
+    }
+
+    // Wraps existing pixel data, which must hold exactly width * height pixels
+    pub fn from_vec(width: usize, height: usize, data: Vec<P>) -> Self {
+        if data.len() != width * height {
+            panic!("Pixel data doesn't match image dimensions");
+        }
+        Image {width, height, data}
+    }
+
+    // Creates an image by calling the function for every (x, y) position
+    pub fn from_fn(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> P) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(f(x, y));
+            }
+        }
+        Image {width, height, data}
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Whether another image has the same width and height
+    pub fn same_dimensions<Q: Pixel>(&self, other: &Image<Q>) -> bool {
+        self.width == other.width && self.height == other.height
+    }
+
+    // Reads the pixel at (x, y)
+    pub fn get(&self, x: usize, y: usize) -> P {
+        self.data[y * self.width + x]
+    }
+
+    // Replaces the pixel at (x, y)
+    pub fn put(&mut self, x: usize, y: usize, pixel: P) {
+        self.data[y * self.width + x] = pixel;
+    }
+
+    // Iterates over rows from top to bottom
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[P]> {
+        self.data.chunks(self.width.max(1))
+    }
+
+    // Builds a new image by applying a function to every pixel
+    pub fn map<Q: Pixel>(&self, f: impl FnMut(P) -> Q) -> Image<Q> {
+        Image {width: self.width, height: self.height, data: self.data.iter().copied().map(f).collect()}
+    }
+
+    // Builds a new image from matching pixels of two images with the same dimensions
+    pub fn zip_map<Q: Pixel, R: Pixel>(&self, other: &Image<Q>, mut f: impl FnMut(P, Q) -> R) -> Image<R> {
+        if !self.same_dimensions(other) {
+            panic!("Images have different dimensions");
+        }
+        let data = self.data.iter().zip(other.data.iter()).map(|(a, b)| f(*a, *b)).collect();
+        Image {width: self.width, height: self.height, data}
+    }
+
+    // Converts every pixel to another layout or channel type
+    pub fn convert<Q: Pixel>(&self) -> Image<Q> {
+        self.map(|pixel| pixel.convert())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::{Bgr, Gray};
+
+    #[test]
+    fn test_rows_and_positions() {
+        // 3x2 image where each value encodes its position
+        let image = Image::from_fn(3, 2, |x, y| Gray {value: (y * 10 + x) as u8});
+        assert_eq!(image.get(2, 1).value, 12);
+        assert_eq!(image.rows().nth(1).unwrap().iter().map(|p| p.value).collect::<Vec<_>>(), vec![10, 11, 12]);
+        assert_eq!(image.rows().next_back().unwrap()[0].value, 10);
+    }
+
+    #[test]
+    fn test_map_and_zip_map() {
+        let first = Image::new(2, 2, Gray {value: 3u8});
+        let second = Image::new(2, 2, Bgr {blue: 1u8, green: 2, red: 4});
+
+        // Closures see pixels of both images in the same position
+        let combined = first.zip_map(&second, |a, b| Gray {value: a.value * b.red});
+        assert!(combined.data.iter().all(|p| p.value == 12));
+        assert!(combined.map(|p| Gray {value: p.value * 2}).data.iter().all(|p| p.value == 24));
+    }
+
+    #[test]
+    #[should_panic(expected = "Images have different dimensions")]
+    fn test_zip_map_checks_dimensions() {
+        let first = Image::new(2, 2, Gray {value: 0u8});
+        let second = Image::new(4, 1, Gray {value: 0u8});
+        first.zip_map(&second, |a, _| a);
+    }
+}
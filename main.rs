@@ -6,7 +6,45 @@ use std::fs;
 use std::io;
 use std::io::Read;
 use std::io::Write;
-use std::fmt;
+
+mod adjust;
+mod blur;
+mod canvas;
+mod channel;
+mod cli;
+mod color;
+mod compose;
+mod convolve;
+mod cube;
+mod curves;
+mod edges;
+mod font;
+mod geometry;
+mod histogram;
+mod hue;
+mod image;
+mod mixer;
+mod morphology;
+mod pixel;
+mod rank;
+mod resample;
+mod resize;
+mod rotate;
+mod sharpen;
+mod smooth;
+mod split;
+mod swizzle;
+mod view;
+mod warp;
+
+use channel::Channel;
+use compose::GridLayout;
+use geometry::Orientation;
+use image::Image;
+use mixer::ChannelMixer;
+use pixel::{Bgr, Bgra, Pixel};
+use split::Component;
+use view::{View, ViewMut};
 
 // Struct to represent TGA header data
 #[derive(PartialEq, Debug)]
@@ -19,8 +57,22 @@ struct Header {
     image_details: [u8; 10],
 }
 
-// Converts TGA header instance to byte array
 impl Header {
+    // Header for a new uncompressed 24-bit image, stored bottom row first
+    fn new(width: usize, height: usize) -> Header {
+        let mut header = Header {
+            id_length: 0,
+            type_map: 0,
+            image_type: 2,
+            color_map_details: [0; 5],
+            image_details: [0; 10],
+        };
+        header.set_dimensions(width, height);
+        header.image_details[8] = 24;
+        header
+    }
+
+    // Converts TGA header instance to byte array
     fn to_bytes(&self) -> [u8; 18] {
         let mut bytes = [0u8; 18];
         bytes[0] = self.id_length;
@@ -30,36 +82,70 @@ impl Header {
         bytes[8..18].copy_from_slice(&self.image_details);
         bytes
     }
-}
 
-// Struct that represents pixel data
-struct Pixels {
-    data: Vec<Pixel>,
+    // Image width, stored little-endian in bytes 4 and 5 of the image details
+    fn width(&self) -> usize {
+        u16::from_le_bytes([self.image_details[4], self.image_details[5]]) as usize
+    }
+
+    // Image height, stored little-endian in bytes 6 and 7 of the image details
+    fn height(&self) -> usize {
+        u16::from_le_bytes([self.image_details[6], self.image_details[7]]) as usize
+    }
+
+    // Whether the first stored row is the top of the image (bit 5 of the image descriptor)
+    // TGA files store the bottom row first unless this bit is set
+    fn is_top_down(&self) -> bool {
+        self.image_details[9] & 0x20 != 0
+    }
+
+    // Updates the width and height fields, which hold at most 65535
+    fn set_dimensions(&mut self, width: usize, height: usize) {
+        assert!(width <= u16::MAX as usize && height <= u16::MAX as usize, "Image is too large for a TGA header: {}x{}", width, height);
+        self.image_details[4..6].copy_from_slice(&(width as u16).to_le_bytes());
+        self.image_details[6..8].copy_from_slice(&(height as u16).to_le_bytes());
+    }
 }
 
 // Struct that represents data (header and pixels)
-struct Data {
+// Pixels are 8-bit BGR by default, any other Pixel layout can be used between operations
+struct Data<P: Pixel = Bgr> {
     header: Header,
-    pixels: Pixels,
+    pixels: Image<P>,
 }
 
-// Struct representing a single BGR pixel
-#[derive(PartialEq, Debug)]
-#[derive(Clone)]
-struct Pixel {
-    blue: u8,
-    green: u8,
-    red: u8,
+impl<P: Pixel> Data<P> {
+    // Converts an image to a different pixel layout or channel type, keeping its header
+    fn convert<Q: Pixel>(&self) -> Data<Q> {
+        Data {header: self.header.clone(), pixels: self.pixels.convert()}
+    }
+
+    // New image with this image's header, with the width and height updated to match the pixels
+    fn with_pixels<Q: Pixel>(&self, pixels: Image<Q>) -> Data<Q> {
+        let mut header = self.header.clone();
+        header.set_dimensions(pixels.width(), pixels.height());
+        Data {header, pixels}
+    }
 }
 
 // Loads a TGA file and returns TGAData
 fn read_tga(file_path: &str) -> Result<Data, io::Error> {
+    read_tga_as(file_path)
+}
+
+// Loads a TGA file into any pixel layout, e.g. Bgra to keep the alpha channel of 32-bit files
+fn read_tga_as<P: Pixel>(file_path: &str) -> Result<Data<P>, io::Error> {
     let mut data = Vec::new(); // Stores binary TGA image data
     let mut file = fs::File::open(file_path)?; // Opens file
 
     // Read TGA header
     file.read_to_end(&mut data)?;
 
+    // Make sure the file is long enough to hold a header
+    if data.len() < 18 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "TGA file is too short"));
+    }
+
     // Split data into header and pixels
     let header = &data[0..18];
     let pixels = &data[18..];
@@ -76,34 +162,57 @@ fn read_tga(file_path: &str) -> Result<Data, io::Error> {
         ],
     };
 
-    // Place pixel data into BGR pixels
-    let pixels = make_pixels(pixels);
+    // 24-bit files store BGR, 32-bit files store BGRA
+    let bytes_per_pixel = match header.image_details[8] {
+        24 => 3,
+        32 => 4,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Only 24 and 32-bit TGA files are supported")),
+    };
+
+    // Place pixel data into BGRA pixels, skipping the image ID field
+    let pixel_count = header.width() * header.height();
+    let mut pixels = make_pixels(pixels.get(header.id_length as usize..).unwrap_or(&[]), bytes_per_pixel);
+
+    // Make sure there is a pixel for every position (anything after them, like a TGA footer, is ignored)
+    if pixels.len() < pixel_count {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "TGA pixel data is shorter than header dimensions"));
+    }
+    pixels.truncate(pixel_count);
 
     // Creates Data struct w/ header and pixel data
-    Ok(Data {header, pixels: Pixels {data: pixels}, })
+    let pixels = Image::from_vec(header.width(), header.height(), pixels).convert();
+    Ok(Data {header, pixels})
 }
 
-// Turns pixel data into BGR pixels
-fn make_pixels(pixel_data: &[u8]) -> Vec<Pixel> {
-    let mut bgr_pixels = Vec::new(); // Holds pixel data
+// Turns pixel data into BGRA pixels, opaque unless the file stores alpha
+fn make_pixels(pixel_data: &[u8], bytes_per_pixel: usize) -> Vec<Bgra> {
+    let mut bgra_pixels = Vec::new(); // Holds pixel data
     let mut index = 0;
     while index < pixel_data.len() {
-        // Ensure that there are at least 3 bytes remaining in the slice
-        if index + 2 < pixel_data.len() {
+        // Ensure that there are enough bytes remaining in the slice for a whole pixel
+        if index + bytes_per_pixel <= pixel_data.len() {
             // Add each color to Pixel vector
             let blue = pixel_data[index];
             let green = pixel_data[index + 1];
             let red = pixel_data[index + 2];
-            bgr_pixels.push(Pixel {blue, green, red});
+            let alpha = if bytes_per_pixel == 4 { pixel_data[index + 3] } else { 255 };
+            bgra_pixels.push(Bgra {blue, green, red, alpha});
         }
-        // Move to next BGR pixel
-        index += 3;
+        // Move to next pixel
+        index += bytes_per_pixel;
     }
-    bgr_pixels
+    bgra_pixels
 }
 
 // Function that writes a new TGA file
-fn write_tga(file_path: &str, data: Data) -> Result<(), io::Error> {
+// Channels are quantized to 8 bits here, so higher precision images are only rounded once
+// Pixel layouts with alpha are written as 32-bit files, everything else as 24-bit
+fn write_tga<P: Pixel>(file_path: &str, data: Data<P>) -> Result<(), io::Error> {
+    // The header stores the width and height as 16-bit values
+    if data.pixels.width() > u16::MAX as usize || data.pixels.height() > u16::MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image is too large for a TGA file"));
+    }
+
     // Attempt to create a new file at specified path
     let file_result = fs::File::create(file_path);
 
@@ -113,27 +222,27 @@ fn write_tga(file_path: &str, data: Data) -> Result<(), io::Error> {
             // Create a vector to store pixel data
             let mut pixel_data = Vec::new();
             for pixel in &data.pixels.data {
-                // Append blue, green, and red components to pixel data vector
-                pixel_data.push(pixel.blue);
-                pixel_data.push(pixel.green);
-                pixel_data.push(pixel.red);
+                // Append blue, green, red (and alpha) components to pixel data vector
+                let bgra = pixel.to_bgra();
+                pixel_data.push(u8::from_f32(bgra[0]));
+                pixel_data.push(u8::from_f32(bgra[1]));
+                pixel_data.push(u8::from_f32(bgra[2]));
+                if P::has_alpha() {
+                    pixel_data.push(u8::from_f32(bgra[3]));
+                }
             }
 
-            // Attempt to write TGA header to file
-            let header_write_result = file.write_all(&data.header.to_bytes());
-
-            // Check if writing header was successful
-            if let Err(e) = header_write_result {
-                return Err(e); // Return an error if writing the header fails
-            }
+            // Header matching the pixel data (no image ID, bit depth and alpha bits of the descriptor)
+            let mut header = data.header.clone();
+            header.id_length = 0;
+            header.image_details[8] = if P::has_alpha() { 32 } else { 24 };
+            header.image_details[9] = (header.image_details[9] & 0xF0) | if P::has_alpha() { 8 } else { 0 };
 
-            // Attempt to write pixel data to file
-            let pixel_data_write_result = file.write_all(&pixel_data);
+            // Write TGA header to file, returning an error if it fails
+            file.write_all(&header.to_bytes())?;
 
-            // Check if writing pixel data was successful
-            if let Err(e) = pixel_data_write_result {
-                return Err(e); // Return an error if writing the pixel data fails
-            }
+            // Write pixel data to file, returning an error if it fails
+            file.write_all(&pixel_data)?;
 
             Ok(()) // Return success if all operations are successful
         }
@@ -141,277 +250,83 @@ fn write_tga(file_path: &str, data: Data) -> Result<(), io::Error> {
     }
 }
 
-// Multiply blending operation
-fn multiply_blend(top_pixels: &Pixels, bottom_pixels: &Pixels) -> Vec<Pixel> {
-    // Ensure both layers have the same dimensions
-    if top_pixels.data.len() != bottom_pixels.data.len() {
-        panic!("Layers have different dimensions.");
-    }
-    let mut multiplied_pixels = Vec::new(); // Holds new pixel data
+// Applies a blend formula to each color channel of two pixels
+// Channels are blended as normalized floats, so any pixel layout and channel type can be used
+// Alpha, if present, is taken from the bottom pixel
+fn blend_pixels<P: Pixel>(top_pixel: P, bottom_pixel: P, blend: impl Fn(f32, f32) -> f32) -> P {
+    P::from_fn(|i| {
+        if i < P::COLOR_CHANNELS {
+            let top = top_pixel.channel(i).to_f32();
+            let bottom = bottom_pixel.channel(i).to_f32();
+            Channel::from_f32(blend(top, bottom))
+        }
+        else {
+            bottom_pixel.channel(i)
+        }
+    })
+}
 
-    // Get reference to the pixel data for both layers
-    let top_data = &top_pixels.data;
-    let bottom_data = &bottom_pixels.data;
+// Blend formulas for one normalized channel of the top and bottom layers
 
-    for i in 0..top_data.len() {
-        let top_pixel = &top_data[i];
-        let bottom_pixel = &bottom_data[i];
+// Multiply color channels of the two pixels (takes care of 0 <= value <= 255 constraint)
+fn multiply_channel(top: f32, bottom: f32) -> f32 {
+    top * bottom
+}
 
-        // Multiply color channels of the two pixels (takes care of 0 <= value <= 255 constraint)
-        let blue = (top_pixel.blue as f32 * bottom_pixel.blue as f32 / 255.0).round() as u8;
-        let green = (top_pixel.green as f32 * bottom_pixel.green as f32 / 255.0).round() as u8;
-        let red = (top_pixel.red as f32 * bottom_pixel.red as f32 / 255.0).round() as u8;
+// Subtract top layer pixels from bottom layer pixels, stopping at 0
+fn subtract_channel(top: f32, bottom: f32) -> f32 {
+    (bottom - top).max(0.0)
+}
 
-        // Create new pixel with result
-        let modified_pixel = Pixel {blue, green, red};
+// Invert both channels, multiply them and invert the result
+fn screen_channel(top: f32, bottom: f32) -> f32 {
+    1.0 - (1.0 - top) * (1.0 - bottom)
+}
 
-        // Add the new pixel to the multiplied_pixels vector
-        multiplied_pixels.push(modified_pixel);
+// Check if bottom layer pixel intensity is less than half (128 for 8-bit channels)
+// If true, use the formula for the Multiply blending mode, otherwise use Screen
+fn overlay_channel(top: f32, bottom: f32) -> f32 {
+    if bottom < 0.5 {
+        2.0 * top * bottom
+    }
+    else {
+        1.0 - 2.0 * (1.0 - top) * (1.0 - bottom)
     }
+}
 
-    multiplied_pixels
+// Multiply blending operation
+fn multiply_blend<P: Pixel>(top_pixels: &Image<P>, bottom_pixels: &Image<P>) -> Image<P> {
+    let mut result = bottom_pixels.clone();
+    blend_region(&top_pixels.as_view(), &mut result.as_view_mut(), multiply_channel);
+    result
 }
 
 // Implements Subtract blending mode
 // Top layer is subtracted FROM the bottom layer
-// Currently using Multiply blending logic
-fn subtract_blend(top_pixels: &Pixels, bottom_pixels: &Pixels) -> Vec<Pixel> {
-    // Ensure both layers have the same dimensions
-    if top_pixels.data.len() != bottom_pixels.data.len() {
-        panic!("Layers have different dimensions.");
-    }
-
-    let mut subtracted_pixels = Vec::new();
-
-    // Get reference to the pixel data for both layers
-    let top_data = &top_pixels.data;
-    let bottom_data = &bottom_pixels.data;
-
-    for i in 0..top_data.len() {
-        let top_pixel = &top_data[i];
-        let bottom_pixel = &bottom_data[i];
-
-        // Subtract top layer pixels from bottom layer pixels (takes care of 0 <= value <= 255 constraint)
-        let blue = bottom_pixel.blue.saturating_sub(top_pixel.blue);
-        let green = bottom_pixel.green.saturating_sub(top_pixel.green);
-        let red = bottom_pixel.red.saturating_sub(top_pixel.red);
-
-        // Create a new pixel with the result
-        let modified_pixel = Pixel { blue, green, red };
-
-        // Add the new pixel to the subtracted_pixels vector
-        subtracted_pixels.push(modified_pixel);
-    }
-
-    subtracted_pixels
+fn subtract_blend<P: Pixel>(top_pixels: &Image<P>, bottom_pixels: &Image<P>) -> Image<P> {
+    let mut result = bottom_pixels.clone();
+    blend_region(&top_pixels.as_view(), &mut result.as_view_mut(), subtract_channel);
+    result
 }
 
 // Implements screen blending mode
-fn screen_blend(top_pixels: &Pixels, bottom_pixels: &Pixels) -> Vec<Pixel> {
-    // Ensure both layers have the same dimensions
-    if top_pixels.data.len() != bottom_pixels.data.len() {
-        panic!("Layers have different dimensions");
-    }
-    let mut screen_pixels = Vec::new();
-    // Get a reference to the pixel data for both layers
-    let top_data = &top_pixels.data;
-    let bottom_data = &bottom_pixels.data;
-
-    // For each pixel
-    for i in 0..top_data.len() {
-        let top_pixel = &top_data[i];
-        let bottom_pixel = &bottom_data[i];
-
-        // Find inverted values for blue channel
-        let inverted_top_blue = 255 - top_pixel.blue;
-        let inverted_bottom_blue = 255 - bottom_pixel.blue;
-        let blue = 255 - ((inverted_top_blue as f32* inverted_bottom_blue as f32 / 255.0).round() as u8);
-
-        // Find inverted values for green channel
-        let inverted_top_green = 255 - top_pixel.green;
-        let inverted_bottom_green = 255 - bottom_pixel.green;
-        let green = 255 - ((inverted_top_green as f32 * inverted_bottom_green as f32 / 255.0).round() as u8);
-
-        // Find inverted values for red channel
-        let inverted_top_red = 255 - top_pixel.red;
-        let inverted_bottom_red = 255 - bottom_pixel.red;
-        let red = 255 - ((inverted_top_red as f32 * inverted_bottom_red as f32 / 255.0).round() as u8);
-
-        // Create a new pixel with the result
-        let screen_pixel = Pixel {blue, green, red};
-
-        // Add modified pixel to the screen_pixels vector
-        screen_pixels.push(screen_pixel);
-    }
-
-    screen_pixels
+fn screen_blend<P: Pixel>(top_pixels: &Image<P>, bottom_pixels: &Image<P>) -> Image<P> {
+    let mut result = bottom_pixels.clone();
+    blend_region(&top_pixels.as_view(), &mut result.as_view_mut(), screen_channel);
+    result
 }
 
 // Overlay blending mode
-fn overlay_blend(top_pixels: &Pixels, bottom_pixels: &Pixels) -> Vec<Pixel> {
-    // Ensure both layers have same dimensions
-    if top_pixels.data.len() != bottom_pixels.data.len() {
-        panic!("Layers don't have same dimensions");
-    }
-
-    let mut overlay_pixels = Vec::new(); // Holds overlay pixel data
-
-    // Get reference to the pixel data for both layers
-    let top_data = &top_pixels.data;
-    let bottom_data = &bottom_pixels.data;
-
-    // For each top and bottom pixel
-    for i in 0..top_data.len() {
-        let top_pixel = &top_data[i];
-        let bottom_pixel = &bottom_data[i];
-
-        let red;
-        let blue;
-        let green;
-
-        // Check if bottom layer pixel intensity is less than 128
-        // If true, use the formula for the Multiply blending mode
-        if bottom_pixel.blue < 128 {
-            blue = (2.0 * top_pixel.blue as f32 * bottom_pixel.blue as f32 / 255.0).round() as u8;
-        }
-        else {
-            blue = (255.0 - 2.0 * (255.0 - top_pixel.blue as f32) * (255.0 - bottom_pixel.blue as f32) / 255.0).round() as u8;
-        }
-        if bottom_pixel.green < 128 {
-            green = (2.0 * top_pixel.green as f32 * bottom_pixel.green as f32 / 255.0).round() as u8;
-        }
-        else {
-            green = (255.0 - 2.0 * (255.0 - top_pixel.green as f32) * (255.0 - bottom_pixel.green as f32) / 255.0).round() as u8;
-        }
-        if bottom_pixel.red < 128 {
-            red = (2.0 * top_pixel.red as f32 * bottom_pixel.red as f32 / 255.0).round() as u8;
-        }
-        else {
-             red = (255.0 - 2.0 * (255.0 - top_pixel.red as f32) * (255.0 - bottom_pixel.red as f32) / 255.0).round() as u8;
-        }
-
-        // Create new pixel with result
-        let new_pixel = Pixel {blue, green, red};
-
-        // Add new pixel to overlay_pixels vector
-        overlay_pixels.push(new_pixel);
-    }
-
-    overlay_pixels
+fn overlay_blend<P: Pixel>(top_pixels: &Image<P>, bottom_pixels: &Image<P>) -> Image<P> {
+    let mut result = bottom_pixels.clone();
+    blend_region(&top_pixels.as_view(), &mut result.as_view_mut(), overlay_channel);
+    result
 }
 
-// Combines image channels
-fn combine_channels(blue_channel: &Data, green_channel: &Data, red_channel: &Data) -> Data{
-    // Ensure all layers have the same dimensions
-    if blue_channel.pixels.data.len() != green_channel.pixels.data.len() || blue_channel.pixels.data.len() != red_channel.pixels.data.len() {
-        panic!("Channels don't have same dimensions");
-    }
-
-    let mut combined_pixels = Vec::new(); // Vector storing new pixels
-
-    // For each pixel
-    for i in 0..blue_channel.pixels.data.len() {
-
-        // Create new pixel with combined channels
-        let combined_channels = Pixel {
-            blue: blue_channel.pixels.data[i].blue,
-            green: green_channel.pixels.data[i].green,
-            red: red_channel.pixels.data[i].red,
-        };
-
-        // Add new pixel to combined_pixels vector
-        combined_pixels.push(combined_channels);
-    }
-
-    // New image, with old image header and new pixel data
-    let new_image = Data {
-        header: blue_channel.header.clone(),
-        pixels: Pixels {data: combined_pixels}
-    };
-
-    new_image
-}
-
-fn flip(image: &Data) -> Vec<Pixel> {
-    let mut flipped_pixels = Vec::new(); // Vector to store flipped pixel data
-
-    // Extract width and height from header
-    let width = image.header.image_details[0] as usize;
-    let height = image.header.image_details[1] as usize;
-
-    // Loop through each row of the original image
-    for i in 0..height {
-        // Loop through each column of the original image
-        for j in 0..width {
-            // Calculate index for flipped image
-            let index = (height - 1 - i) * width + j;
-             // Append pixel at desired index to flipped pixel vector
-            flipped_pixels.push(image.pixels.data[index].clone());
-        }
-    }
-
-    flipped_pixels
-}
-
-// Combine images
-fn combine_images(images: [&Data; 4]) -> Data {
-    // Make sure images have same dimensions
-    let width = images[0].header.image_details[8] as usize;
-    let height = images[0].header.image_details[9] as usize;
-
-    for i in 1..4 {
-        if images[i].header.image_details[8] as usize != width || images[i].header.image_details[9] as usize != height {
-            panic!("Images have different dimensions");
-        }
-    }
-
-    let mut combined_pixels = Vec::new();  // New vector to store combined pixel data
-
-    // Loop through each pixel and combine quadrants
-    for i in 0..height {
-        for j in 0..width {
-            let quadrant_index = if j < width / 2 {
-                if i < height / 2 {
-                    0 // Top-left
-                } else {
-                    2 // Bottom-left
-                }
-            } else {
-                if i < height / 2 {
-                    1 // Top-right
-                } else {
-                    3 // Bottom-right
-                }
-            };
-
-            let pixel = &images[quadrant_index].pixels.data[i * width + j];
-
-            // Add the combined pixel to the vector
-            combined_pixels.push(pixel.clone());
-        }
-    }
-
-    // New header, reflects image dimension changes
-    let mut combined_header = Header {
-        id_length: 0,
-        type_map: 0,
-        image_type: 2,
-        color_map_details: [0; 5],
-        image_details: [0; 10],
-    };
-
-    combined_header.image_details[5] = 4;
-    combined_header.image_details[6] = 0;
-    combined_header.image_details[7] = 4;
-    combined_header.image_details[8] = 24;
-
-    // New Data struct for combined image
-    let combined_image = Data {
-        header: combined_header,
-        pixels: Pixels {data: combined_pixels},
-    };
-
-    combined_image
+// Blends a top region onto a bottom region of the same size in place
+// Used with one of the channel formulas above, e.g. blend_region(&top, &mut bottom, multiply_channel)
+fn blend_region<P: Pixel>(top_pixels: &View<P>, bottom_pixels: &mut ViewMut<P>, blend: fn(f32, f32) -> f32) {
+    bottom_pixels.zip_map_in_place(top_pixels, |bottom, top| *bottom = blend_pixels(top, *bottom, blend));
 }
 
 // Part 1 function
@@ -434,7 +349,7 @@ fn part1() {
     // Data instance result
     let result_data = Data {
         header: top_layer.header, // Uses header from top layer
-        pixels: Pixels { data: blended_pixels },
+        pixels: blended_pixels,
     };
 
     // Save result as "part1.tga" in output folder
@@ -462,7 +377,7 @@ fn part2() {
     // Create TGAData instance for the result
     let result_data = Data {
         header: top_layer.header, // Uses header from top layer
-        pixels: Pixels {data: blended_pixels},
+        pixels: blended_pixels,
     };
 
     // Save result as "part2.tga" in output folder
@@ -492,7 +407,7 @@ fn part3() {
     // Create TGAData instance for Multiply result
     let multiply_result = Data {
         header: top_layer.header, // Uses header from top layer
-        pixels: Pixels {data: blended_pixels},
+        pixels: blended_pixels,
     };
 
     // Load top layer TGA file (text.tga)
@@ -509,7 +424,7 @@ fn part3() {
     // Create TGAData instance for Screen result
     let output_data = Data {
         header: top_layer.header, // Uses header from the top layer
-        pixels: Pixels {data: blended_pixels},
+        pixels: blended_pixels,
     };
 
     // Save final result in the output folder
@@ -523,10 +438,11 @@ fn part3() {
 fn part4() {
     // Multiply
     // Load top layer TGA file (layer2.tga)
-    let top_layer = read_tga("input/layer2.tga").expect("Failed to load top layer");
+    // Layers are kept as f32 so the intermediate result isn't rounded before subtracting
+    let top_layer = read_tga("input/layer2.tga").expect("Failed to load top layer").convert::<Bgr<f32>>();
 
     // Load bottom layer TGA file (circles.tga)
-    let bottom_layer = read_tga("input/circles.tga").expect("Failed to load bottom layer");
+    let bottom_layer = read_tga("input/circles.tga").expect("Failed to load bottom layer").convert::<Bgr<f32>>();
 
     // Make sure both layers have the same dimensions
     if top_layer.pixels.data.len() != bottom_layer.pixels.data.len() {
@@ -539,12 +455,12 @@ fn part4() {
     // Create TGAData instance for result
     let result_data = Data {
         header: top_layer.header, // Uses header from the top layer
-        pixels: Pixels { data: blended_pixels },
+        pixels: blended_pixels,
     };
 
     // Subtract
     // Load top layer TGA file (pattern2.tga)
-    let top_layer = read_tga("input/pattern2.tga").expect("Failed to load top layer");
+    let top_layer = read_tga("input/pattern2.tga").expect("Failed to load top layer").convert::<Bgr<f32>>();
 
     // Set bottom layer to result_data
     let bottom_layer = result_data;
@@ -560,10 +476,10 @@ fn part4() {
     // Create TGAData instance for result
     let output_data = Data {
         header: top_layer.header, // Uses header from the top layer
-        pixels: Pixels {data: blended_pixels},
+        pixels: blended_pixels,
     };
 
-    // Save result in output folder
+    // Save result in output folder (only quantized back to 8 bits here)
     write_tga("output/part4.tga", output_data).expect("Failed to save the result as TGA file");
 }
 
@@ -587,7 +503,7 @@ fn part5() {
     // Create TGAData instance for result
     let result_data = Data {
         header: top_layer.header, // Uses header from the top layer
-        pixels: Pixels {data: blended_pixels},
+        pixels: blended_pixels,
     };
 
     // Save result as "part1.tga" in output folder
@@ -598,16 +514,17 @@ fn part5() {
 // Load “car.tga” and add 200 to the green channel
 fn part6() {
     // Load car.tga
-    let mut image_to_modify = read_tga("input/car.tga").expect("Failed to load car.tga");
+    let image_to_modify = read_tga("input/car.tga").expect("Failed to load car.tga");
 
-    // Loop through pixels in car image, adding 200 to green channel
-    for pixel in &mut image_to_modify.pixels.data {
-        let modified_green = pixel.green.saturating_add(200); // Saturating add ensures result remains <255.
-        pixel.green = modified_green;
-    }
+    // Add 200 (out of 255) to the green channel, results above 255 are clamped
+    let add_green = ChannelMixer::from_rgb([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 200.0 / 255.0],
+        [0.0, 0.0, 1.0, 0.0],
+    ]);
 
     // Save modified image
-    write_tga("output/part6.tga", image_to_modify).expect("Failed to save image.");
+    write_tga("output/part6.tga", mixer::mix_channels(&image_to_modify, &add_green)).expect("Failed to save image.");
 }
 
 // Part 7 function
@@ -615,18 +532,17 @@ fn part6() {
 // increase the intensity of any red in the image, while negating any blue it may have
 fn part7() {
     // Load car.tga
-    let mut image_to_modify = read_tga("input/car.tga").expect("Failed to load car.tga.");
-
-    // Loop through pixels in car image, multiplying red channel by 4 and negating blue channel
-    for pixel in &mut image_to_modify.pixels.data {
-        let modified_blue = 0; // Blue is negated
-        pixel.blue = modified_blue;
-        let modified_red = pixel.red.saturating_mul(4); // Multiply red channel by 4, saturating ensures result <255
-        pixel.red = modified_red;
-    }
+    let image_to_modify = read_tga("input/car.tga").expect("Failed to load car.tga.");
+
+    // Multiply red by 4 (clamped at 255) and negate blue
+    let boost_red = ChannelMixer::from_rgb([
+        [4.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0],
+    ]);
 
     // Save modified image
-    write_tga("output/part7.tga", image_to_modify).expect("Failed to save image");
+    write_tga("output/part7.tga", mixer::mix_channels(&image_to_modify, &boost_red)).expect("Failed to save image");
 }
 
 // Part 8 function
@@ -635,81 +551,13 @@ fn part7() {
 fn part8() {
     let image = read_tga("input/car.tga").expect("Failed to load car.tga");
 
-    // Loop through pixels in car image, extracting red channel
-    let mut red_channel_pixels = Vec::new();
-
-    for i in 0..image.pixels.data.len() {
-        let current_pixel = &image.pixels.data[i]; 
-
-        // Extract red channel, set blue and green to 0
-        let blue = current_pixel.red;
-        let green = current_pixel.red;
-        let red = current_pixel.red;
-
-        // Create new pixel with the extracted red channel
-        let modified_pixel = Pixel {blue, green, red};
-
-        // Add new pixel to red_channel_pixels vector
-        red_channel_pixels.push(modified_pixel);
-    }
-
-    let result_data = Data {
-        header: image.header.clone(), // Use clone to avoid moving header
-        pixels: Pixels { data: red_channel_pixels },
-    };
-
-    write_tga("output/part8_r.tga", result_data).expect("Failed to save image.");
-
-    // Handle green channel
-    let mut green_channel_pixels = Vec::new();
-
-    for i in 0..image.pixels.data.len() {
-        let current_pixel = &image.pixels.data[i];
-
-        // Green channel, set blue and green to 0
-        let blue = current_pixel.green;
-        let green = current_pixel.green;
-        let red = current_pixel.green;
-
-        // Create a new pixel with extracted red channel
-        let modified_pixel = Pixel {blue, green, red};
-
-        // Add the new pixel to red_channel_pixels vector
-        green_channel_pixels.push(modified_pixel);
-    }
-
-    let result_data = Data {
-        header: image.header.clone(),
-        pixels: Pixels { data: green_channel_pixels },
-    };
-
-    write_tga("output/part8_g.tga", result_data).expect("Failed to save image.");
-
-    // Blue channel
-    let mut blue_channel_pixels = Vec::new();
-
-    for i in 0..image.pixels.data.len() {
-        let current_pixel = &image.pixels.data[i]; 
-
-        let blue = current_pixel.blue;
-        let green = current_pixel.blue;
-        let red = current_pixel.blue;
-
-        // Create a new pixel with the extracted blue channel
-        let modified_pixel = Pixel {blue, green, red};
-
-        // Add the new pixel to the blue_channel_pixels vector
-        blue_channel_pixels.push(modified_pixel);
+    // Each channel becomes a grayscale image
+    let channels = [("r", Component::Red), ("g", Component::Green), ("b", Component::Blue)];
+    let split_images = split::split_channels::<Bgr, Bgr>(&image, &channels.map(|(_, component)| component));
+    for ((name, _), channel_image) in channels.iter().zip(split_images) {
+        write_tga(&format!("output/part8_{}.tga", name), channel_image).expect("Failed to save image.");
     }
-
-    let result_data = Data {
-        header: image.header.clone(), // Use clone to avoid moving header
-        pixels: Pixels { data: blue_channel_pixels },
-    };
-
-    write_tga("output/part8_b.tga", result_data).expect("Failed to save image");
-
-}    
+}
 
 // Part 9 function
 // Load “layer_red.tga”, “layer_green.tga” and “layer_blue.tga”, and combine the three files into
@@ -722,7 +570,11 @@ fn part9() {
     let red_channel = read_tga("input/layer_red.tga").expect("Failed to load layer_red.tga");
 
     // Combine the layers and save the result
-    let new_image = combine_channels(&blue_channel, &green_channel, &red_channel);
+    let new_image = split::merge_channels::<Bgr, Bgr>(&[
+        (&blue_channel, Component::Blue, Component::Blue),
+        (&green_channel, Component::Green, Component::Green),
+        (&red_channel, Component::Red, Component::Red),
+    ]).expect("Failed to combine channels");
 
     write_tga("output/part9.tga", new_image).expect("Failed to write TGA file");
 }
@@ -731,13 +583,9 @@ fn part9() {
 // Load “text2.tga”, and rotate it 180 degrees, flipping it upside down
 fn part10() {
     // Load text2.tga
-    let image_to_modify = read_tga("input/text2.tga").expect("Failed to load car.tga");
-    let flipped_pixels = flip(&image_to_modify);
-    let flipped_image_data = Data {
-        header: image_to_modify.header.clone(), // Uses header from text2.tga
-        pixels: Pixels {data: flipped_pixels}, // Flipped pixels
-    };
-    write_tga("output/part10.tga", flipped_image_data).expect("Unable to flip image data");
+    let image_to_modify = read_tga("input/text2.tga").expect("Failed to load text2.tga");
+    let rotated_image_data = geometry::reorient(&image_to_modify, Orientation::Rotate180);
+    write_tga("output/part10.tga", rotated_image_data).expect("Unable to rotate image data");
 }
 
 // Extra credit function
@@ -749,24 +597,44 @@ fn extra_credit() {
     let pattern1_image = read_tga("input/pattern1.tga").expect("Failed to load pattern1.tga");
     let text_image = read_tga("input/text.tga").expect("Failed to load text.tga");
 
-    // Use combined image method
-    let images = [&car_image, &circles_image, &pattern1_image, &text_image];
-    let combined_image = combine_images(images);
-
     // Write the combined image to output/extracredit.tga
+    let combined_image = combine_quadrants(&car_image, &circles_image, &pattern1_image, &text_image);
     write_tga("output/extracredit.tga", combined_image).expect("Failed to write extracredit.tga");
 }
 
-// Test format for pixels 
-impl fmt::Display for Pixel {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Pixel(R: {}, G: {}, B: {})", self.red, self.green, self.blue)
+// Lays the extra credit images out in a 2x2 grid: pattern1 and text on top, car and circles below
+fn combine_quadrants(car: &Data, circles: &Data, pattern1: &Data, text: &Data) -> Data {
+    compose::compose_grid(&[pattern1, text, car, circles], &[], &GridLayout::new(2, Bgr::default()))
+}
+
+fn main() {
+    // With arguments, run the command they name instead of the project parts
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    if !arguments.is_empty() {
+        if let Err(error) = cli::run(&arguments) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
     }
+
+    part1();
+    part2();
+    part3();
+    part4();
+    part5();
+    part6();
+    part7();
+    part8();
+    part9();
+    part10();
+    extra_credit();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
     #[test]
     fn test_part1() {
         // Load the actual result produced by your code
@@ -1069,18 +937,162 @@ mod tests {
             }
         }
     }        
-}
 
-fn main() {
-    part1();
-    part2();
-    part3();
-    part4();
-    part5();
-    part6();
-    part7();
-    part8();
-    part9();
-    part10();
-    extra_credit();
+    #[test]
+    fn test_blends_match_at_every_precision() {
+        // Every combination of two 8-bit values, as one image
+        let top_pixels = Image::from_fn(256, 52, |x, y| Bgr {blue: (y * 5) as u8, green: x as u8, red: (y * 5) as u8});
+        let bottom_pixels = Image::from_fn(256, 52, |x, y| Bgr {blue: x as u8, green: (y * 5) as u8, red: 255 - x as u8});
+        type Blend<C> = fn(&Image<Bgr<C>>, &Image<Bgr<C>>) -> Image<Bgr<C>>;
+        let blends: [Blend<u8>; 4] = [multiply_blend, subtract_blend, screen_blend, overlay_blend];
+        let wide_blends: [Blend<u16>; 4] = [multiply_blend, subtract_blend, screen_blend, overlay_blend];
+        let float_blends: [Blend<f32>; 4] = [multiply_blend, subtract_blend, screen_blend, overlay_blend];
+
+        // Blending at higher precision and quantizing afterwards gives the 8-bit result
+        for i in 0..blends.len() {
+            let expected = blends[i](&top_pixels, &bottom_pixels);
+            let wide = wide_blends[i](&top_pixels.convert(), &bottom_pixels.convert());
+            let float = float_blends[i](&top_pixels.convert(), &bottom_pixels.convert());
+            assert_eq!(wide.convert::<Bgr>(), expected);
+            assert_eq!(float.convert::<Bgr>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_f32_chain_is_not_quantized() {
+        // 10 * 10 / 255 = 0.39, rounded to 0 when stored as 8 bits
+        let layer = Image::new(1, 1, Bgr {blue: 10u8, green: 10, red: 10});
+        let multiplied = multiply_blend(&layer, &layer);
+        let multiplied_float = multiply_blend(&layer.convert::<Bgr<f32>>(), &layer.convert());
+
+        // Screening the result with itself doubles the value that was rounded away
+        let screened = screen_blend(&multiplied, &multiplied).get(0, 0);
+        let screened_float = screen_blend(&multiplied_float, &multiplied_float).get(0, 0);
+        let exact = 1.0 - (1.0 - 10.0 * 10.0 / 255.0 / 255.0f32).powi(2);
+        assert!((screened_float.red - exact).abs() < 1e-6);
+        assert_eq!(screened.red, 0);
+        assert_eq!(screened_float.convert::<Bgr>().red, 1);
+    }
+
+    #[test]
+    fn test_blends_keep_bottom_alpha() {
+        // Alpha isn't blended, the bottom layer's coverage is kept
+        let top = Image::new(1, 1, Bgra {blue: 255u8, green: 128, red: 0, alpha: 10});
+        let bottom = Image::new(1, 1, Bgra {blue: 128u8, green: 255, red: 255, alpha: 200});
+        let multiplied = multiply_blend(&top, &bottom).get(0, 0);
+        assert_eq!(multiplied, Bgra {blue: 128, green: 128, red: 0, alpha: 200});
+    }
+
+    #[test]
+    fn test_blend_region_in_place() {
+        // Multiply only the right half of the bottom layer
+        let top = Image::new(2, 1, Bgr {blue: 128u8, green: 128, red: 128});
+        let mut bottom = Image::new(4, 2, Bgr {blue: 200u8, green: 100, red: 0});
+        blend_region(&top.as_view(), &mut bottom.view_mut(2, 1, 2, 1), multiply_channel);
+
+        let multiplied = Bgr {blue: 100, green: 50, red: 0};
+        let untouched = Bgr {blue: 200, green: 100, red: 0};
+        assert_eq!(bottom.rows().nth(1).unwrap(), [untouched, untouched, multiplied, multiplied]);
+        assert!(bottom.rows().next().unwrap().iter().all(|pixel| *pixel == untouched));
+    }
+
+    #[test]
+    fn test_reorient_updates_header() {
+        // 3x2 image stored bottom row first, as TGA files usually are
+        let header = Header::new(3, 2);
+        let image = Data {header, pixels: Image::from_fn(3, 2, |x, y| Bgr {blue: (y * 3 + x) as u8, green: 0, red: 0})};
+
+        // Rotating clockwise on screen puts the displayed bottom-left pixel (stored first) at the top-left
+        let rotated = geometry::reorient(&image, Orientation::Rotate90);
+        assert_eq!((rotated.header.width(), rotated.header.height()), (2, 3));
+        assert_eq!((rotated.pixels.width(), rotated.pixels.height()), (2, 3));
+        assert_eq!(rotated.pixels.get(0, 2), image.pixels.get(0, 0));
+
+        // Images stored top row first rotate the other way in storage order
+        let mut top_down = image.with_pixels(image.pixels.clone());
+        top_down.header.image_details[9] |= 0x20;
+        let rotated = geometry::reorient(&top_down, Orientation::Rotate90);
+        assert_eq!(rotated.pixels.get(1, 0), image.pixels.get(0, 0));
+    }
+
+    #[test]
+    fn test_tga_round_trip_keeps_alpha() {
+        // 2x1 image with partial transparency, written as a 32-bit file
+        let header = Header::new(2, 1);
+        let pixels = Image::from_vec(2, 1, vec![Bgra {blue: 1u8, green: 2, red: 3, alpha: 0}, Bgra {blue: 4, green: 5, red: 6, alpha: 128}]);
+        let path = std::env::temp_dir().join("image_processor_alpha_round_trip.tga");
+        let path = path.to_str().unwrap();
+        write_tga(path, Data {header, pixels: pixels.clone()}).expect("Failed to write TGA file");
+
+        // Alpha survives when read as BGRA and is dropped when read as BGR
+        let with_alpha = read_tga_as::<Bgra>(path).expect("Failed to read TGA file");
+        assert_eq!(with_alpha.header.image_details[8], 32);
+        assert_eq!(with_alpha.pixels, pixels);
+        let without_alpha = read_tga(path).expect("Failed to read TGA file");
+        assert_eq!(without_alpha.pixels.get(1, 0), Bgr {blue: 4, green: 5, red: 6});
+        fs::remove_file(path).expect("Failed to remove TGA file");
+    }
+
+    #[test]
+    fn test_crop_uses_displayed_rows() {
+        // 2x3 bottom-up image, the first stored row is the bottom of the picture
+        let header = Header::new(2, 3);
+        let image = Data {header, pixels: Image::from_fn(2, 3, |x, y| Bgr {blue: (y * 2 + x) as u8, green: 0, red: 0})};
+
+        // The displayed top row is the last one stored
+        let top_row = canvas::crop(&image, 0, 0, 2, 1);
+        assert_eq!((top_row.header.width(), top_row.header.height()), (2, 1));
+        assert_eq!(top_row.pixels.data, image.pixels.rows().nth(2).unwrap());
+
+        // Rectangles past the bottom edge are cut off
+        let bottom_rows = canvas::crop(&image, 1, 2, 5, 5);
+        assert_eq!((bottom_rows.header.width(), bottom_rows.header.height()), (1, 1));
+        assert_eq!(bottom_rows.pixels.get(0, 0), image.pixels.get(1, 0));
+    }
+
+    #[test]
+    fn test_warp_uses_displayed_rows() {
+        // 1x3 bottom-up image, moving it down on screen moves it towards the start of storage
+        let image = Data {header: Header::new(1, 3), pixels: Image::from_fn(1, 3, |_, y| Bgr {blue: y as u8 + 1, green: 0, red: 0})};
+        let transform = warp::Transform::translate(0.0, 1.0);
+        let moved = warp::warp(&image, &transform, 1, 3, resample::Interpolation::Nearest, resample::Edge::Constant, Bgr::default());
+        let values: Vec<u8> = moved.pixels.data.iter().map(|pixel| pixel.blue).collect();
+        assert_eq!(values, vec![2, 3, 0]);
+    }
+
+    #[test]
+    fn test_combine_quadrants_layout() {
+        // Solid 2x2 images, so each quadrant shows which image it came from
+        let solid = |blue: u8| Data {header: Header::new(2, 2), pixels: Image::new(2, 2, Bgr {blue, green: 0, red: 0})};
+        let (car, circles, pattern1, text) = (solid(1), solid(2), solid(3), solid(4));
+        let combined = combine_quadrants(&car, &circles, &pattern1, &text);
+
+        // Stored bottom row first, so the last stored row is the displayed top
+        assert_eq!((combined.header.width(), combined.header.height()), (4, 4));
+        assert_eq!(combined.pixels.get(0, 3), pattern1.pixels.get(0, 0));
+        assert_eq!(combined.pixels.get(3, 3), text.pixels.get(0, 0));
+        assert_eq!(combined.pixels.get(0, 0), car.pixels.get(0, 0));
+        assert_eq!(combined.pixels.get(3, 0), circles.pixels.get(0, 0));
+    }
+
+    #[test]
+    fn test_header_dimensions() {
+        // Width and height are little-endian u16 values
+        let mut header = Header::new(1, 1);
+        header.set_dimensions(512, 300);
+        assert_eq!(header.image_details[4..8], [0, 2, 44, 1]);
+        assert_eq!((header.width(), header.height()), (512, 300));
+
+        // Images too wide for the header aren't written
+        let wide = Data {header, pixels: Image::new(u16::MAX as usize + 1, 1, Bgr::<u8>::default())};
+        let path = std::env::temp_dir().join("image_processor_too_wide.tga");
+        assert_eq!(write_tga(path.to_str().unwrap(), wide).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    #[should_panic(expected = "Image is too large for a TGA header")]
+    fn test_header_dimensions_too_large() {
+        Header::new(1, 1).set_dimensions(u16::MAX as usize + 1, 1);
+    }
 }
@@ -0,0 +1,135 @@
+// Channel mixer: every output channel is a weighted sum of the input channels plus an offset
+// Covers adding to or scaling channels, swapping them and desaturating with one matrix
+
+use std::io;
+
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::Data;
+
+// Rows are the output red, green, blue and alpha; columns the input red, green, blue and alpha
+// followed by an offset. Values are normalized, so an offset of 1.0 is full intensity
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChannelMixer {
+    pub matrix: [[f32; 5]; 4],
+}
+
+impl ChannelMixer {
+    // Leaves every channel as it is
+    pub fn identity() -> ChannelMixer {
+        ChannelMixer {matrix: [
+            [1.0, 0.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0],
+        ]}
+    }
+
+    // Mixer from a 3x4 matrix of red, green and blue gains plus an offset, leaving alpha alone
+    pub fn from_rgb(matrix: [[f32; 4]; 3]) -> ChannelMixer {
+        let mut mixer = ChannelMixer::identity();
+        for (row, values) in matrix.iter().enumerate() {
+            mixer.matrix[row] = [values[0], values[1], values[2], 0.0, values[3]];
+        }
+        mixer
+    }
+
+    // Mixer from a full 4x5 matrix including alpha
+    pub fn from_rgba(matrix: [[f32; 5]; 4]) -> ChannelMixer {
+        ChannelMixer {matrix}
+    }
+
+    // Every color channel set to the Rec. 601 luma, the same weights used for grayscale elsewhere
+    pub fn grayscale() -> ChannelMixer {
+        let luma = [0.299, 0.587, 0.114, 0.0];
+        ChannelMixer::from_rgb([luma, luma, luma])
+    }
+
+    // Mixer from 12 numbers (a 3x4 matrix) or 20 numbers (4x5), row by row,
+    // separated by commas and/or whitespace, or named grayscale
+    pub fn parse(text: &str) -> Result<ChannelMixer, io::Error> {
+        if text.trim().eq_ignore_ascii_case("grayscale") {
+            return Ok(ChannelMixer::grayscale());
+        }
+        let values = text.split(|c: char| c == ',' || c.is_whitespace()).filter(|value| !value.is_empty()).map(|value| {
+            value.parse::<f32>().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid matrix value: {}", value)))
+        }).collect::<Result<Vec<f32>, io::Error>>()?;
+
+        match values.len() {
+            12 => {
+                let mut matrix = [[0.0; 4]; 3];
+                for (row, chunk) in matrix.iter_mut().zip(values.chunks(4)) {
+                    row.copy_from_slice(chunk);
+                }
+                Ok(ChannelMixer::from_rgb(matrix))
+            }
+            20 => {
+                let mut matrix = [[0.0; 5]; 4];
+                for (row, chunk) in matrix.iter_mut().zip(values.chunks(5)) {
+                    row.copy_from_slice(chunk);
+                }
+                Ok(ChannelMixer::from_rgba(matrix))
+            }
+            count => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Channel mixer needs 12 or 20 values, got {}", count))),
+        }
+    }
+
+    // Mixes one pixel's normalized BGRA values
+    pub fn apply(&self, bgra: [f32; 4]) -> [f32; 4] {
+        let rgba = [bgra[2], bgra[1], bgra[0], bgra[3]];
+        let mixed = self.matrix.map(|row| row[0] * rgba[0] + row[1] * rgba[1] + row[2] * rgba[2] + row[3] * rgba[3] + row[4]);
+        [mixed[2], mixed[1], mixed[0], mixed[3]]
+    }
+}
+
+// Mixes the channels of every pixel
+// Results are rounded and clamped by the pixel's channel type
+pub fn mix_channels_image<P: Pixel>(image: &Image<P>, mixer: &ChannelMixer) -> Image<P> {
+    image.map(|pixel| P::from_bgra(mixer.apply(pixel.to_bgra())))
+}
+
+// Mixes the channels of an image
+pub fn mix_channels<P: Pixel>(image: &Data<P>, mixer: &ChannelMixer) -> Data<P> {
+    image.with_pixels(mix_channels_image(&image.pixels, mixer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Bgr;
+
+    #[test]
+    fn test_matches_saturating_arithmetic() {
+        // Adding to one channel and scaling others gives the same results as 8-bit saturating math
+        let add = ChannelMixer::from_rgb([[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 200.0 / 255.0], [0.0, 0.0, 1.0, 0.0]]);
+        let scale = ChannelMixer::from_rgb([[4.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 0.0]]);
+        for value in 0..=255u8 {
+            let pixel = Bgr {blue: value, green: value, red: value};
+            let added: Bgr = Bgr::from_bgra(add.apply(pixel.to_bgra()));
+            assert_eq!(added, Bgr {blue: value, green: value.saturating_add(200), red: value});
+            let scaled: Bgr = Bgr::from_bgra(scale.apply(pixel.to_bgra()));
+            assert_eq!(scaled, Bgr {blue: 0, green: value, red: value.saturating_mul(4)});
+        }
+    }
+
+    #[test]
+    fn test_swap_and_grayscale() {
+        let swap = ChannelMixer::from_rgb([[0.0, 0.0, 1.0, 0.0], [0.0, 1.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0]]);
+        let image = Image::new(1, 1, Bgr {blue: 10u8, green: 20, red: 30});
+        assert_eq!(mix_channels_image(&image, &swap).get(0, 0), Bgr {blue: 30, green: 20, red: 10});
+
+        let gray = mix_channels_image(&image, &ChannelMixer::grayscale()).get(0, 0);
+        assert!(gray.blue == gray.green && gray.green == gray.red);
+    }
+
+    #[test]
+    fn test_parse() {
+        let mixer = ChannelMixer::parse("1,0,0,0  0, 1, 0, 0.5\n0 0 1 0").unwrap();
+        assert_eq!(mixer.matrix[1], [0.0, 1.0, 0.0, 0.0, 0.5]);
+        assert_eq!(mixer.matrix[3], [0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(ChannelMixer::parse(&["0"; 20].join(",")).unwrap().matrix[3], [0.0; 5]);
+        assert!(ChannelMixer::parse("1 2 3").is_err());
+        assert_eq!(ChannelMixer::parse("Grayscale").unwrap(), ChannelMixer::grayscale());
+        assert!(ChannelMixer::parse("1 2 3 x 0 0 0 0 0 0 0 0").is_err());
+    }
+}
@@ -0,0 +1,340 @@
+// Morphological operations: erosion, dilation and the filters built from them
+// Work on every color channel, so binary masks and grayscale images are handled alike
+// Pixels outside the image are ignored, so the edges are neither grown nor eaten into
+
+use std::io;
+
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::Data;
+
+// Shape of the neighbourhood each pixel is compared with, centered on the pixel
+#[derive(Clone, PartialEq, Debug)]
+pub struct Element {
+    width: usize,
+    height: usize,
+    mask: Vec<bool>,
+    // Whether every cell is set, which allows the faster separable route
+    rectangle: bool,
+}
+
+impl Element {
+    // Element from a mask in row-major order, the width and height must be odd and at least one
+    // cell must be set
+    pub fn custom(width: usize, height: usize, mask: Vec<bool>) -> Element {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            panic!("Element width and height must be odd");
+        }
+        if mask.len() != width * height {
+            panic!("Element mask doesn't match element dimensions");
+        }
+        if !mask.contains(&true) {
+            panic!("Element mask has no cells set");
+        }
+        let rectangle = mask.iter().all(|&set| set);
+        Element {width, height, mask, rectangle}
+    }
+
+    // Every cell of a width x height rectangle
+    pub fn rectangle(width: usize, height: usize) -> Element {
+        Element::custom(width, height, vec![true; width * height])
+    }
+
+    // Cells inside the ellipse that touches the sides of a width x height rectangle
+    pub fn ellipse(width: usize, height: usize) -> Element {
+        let (radius_x, radius_y) = (width as f32 / 2.0, height as f32 / 2.0);
+        let mask = (0..width * height).map(|i| {
+            let dx = (i % width) as f32 + 0.5 - radius_x;
+            let dy = (i / width) as f32 + 0.5 - radius_y;
+            (dx / radius_x).powi(2) + (dy / radius_y).powi(2) <= 1.0
+        }).collect();
+        Element::custom(width, height, mask)
+    }
+
+    // The middle row and middle column of a width x height rectangle
+    pub fn cross(width: usize, height: usize) -> Element {
+        let mask = (0..width * height).map(|i| i % width == width / 2 || i / width == height / 2).collect();
+        Element::custom(width, height, mask)
+    }
+
+    // Element given as <shape>:<width>x<height> with the shape rectangle, ellipse or cross,
+    // such as ellipse:5x5
+    pub fn parse(text: &str) -> Result<Element, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid element: {}", text));
+        let lowercase = text.trim().to_ascii_lowercase();
+        let (shape, size) = lowercase.split_once(':').ok_or_else(invalid)?;
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let width: usize = width.trim().parse().map_err(|_| invalid())?;
+        let height: usize = height.trim().parse().map_err(|_| invalid())?;
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Element width and height must be odd"));
+        }
+
+        match shape.trim() {
+            "rectangle" => Ok(Element::rectangle(width, height)),
+            "ellipse" => Ok(Element::ellipse(width, height)),
+            "cross" => Ok(Element::cross(width, height)),
+            _ => Err(invalid()),
+        }
+    }
+
+    // Offsets from the center of every set cell
+    fn offsets(&self) -> Vec<(isize, isize)> {
+        (0..self.width * self.height).filter(|&i| self.mask[i]).map(|i| {
+            ((i % self.width) as isize - (self.width / 2) as isize, (i / self.width) as isize - (self.height / 2) as isize)
+        }).collect()
+    }
+
+    // The same element upside down, used for images stored bottom row first
+    pub fn flipped_vertically(&self) -> Element {
+        let mask = self.mask.chunks(self.width).rev().flatten().copied().collect();
+        Element {width: self.width, height: self.height, mask, rectangle: self.rectangle}
+    }
+}
+
+// Morphological operations, all built from erosion and dilation
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operation {
+    // Darkest value under the element, shrinks bright areas
+    Erode,
+    // Brightest value under the element, grows bright areas
+    Dilate,
+    // Erode then dilate, removes bright specks smaller than the element
+    Open,
+    // Dilate then erode, fills dark holes smaller than the element
+    Close,
+    // Dilation minus erosion, bright along the outlines of shapes
+    Gradient,
+    // Image minus its opening, the bright details removed by opening
+    TopHat,
+    // Closing minus the image, the dark details removed by closing
+    BlackHat,
+}
+
+impl Operation {
+    // Operation named erode, dilate, open, close, gradient, top-hat or black-hat, ignoring case
+    pub fn parse(name: &str) -> Result<Operation, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "erode" => Ok(Operation::Erode),
+            "dilate" => Ok(Operation::Dilate),
+            "open" => Ok(Operation::Open),
+            "close" => Ok(Operation::Close),
+            "gradient" => Ok(Operation::Gradient),
+            "top-hat" => Ok(Operation::TopHat),
+            "black-hat" => Ok(Operation::BlackHat),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown morphological operation: {}", name))),
+        }
+    }
+}
+
+// Channel-wise minimum or maximum of two values, keeping the alpha of the first
+type Combine = fn([f32; 4], [f32; 4]) -> [f32; 4];
+
+fn minimum(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2]), a[3]]
+}
+
+fn maximum(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2]), a[3]]
+}
+
+// Combines every value with the radius values on either side of it along one line (van Herk/Gil-Werman)
+// The line is cut into blocks as long as the window; running results from the start and from the end
+// of each block cover any window with just two lookups, three operations per value whatever the size
+fn van_herk(line: &[[f32; 4]], radius: usize, combine: Combine, identity: [f32; 4]) -> Vec<[f32; 4]> {
+    let size = 2 * radius + 1;
+    let length = line.len();
+
+    // Pad with the identity so windows can run past both ends, and to a whole number of blocks
+    let padded_length = (length + 2 * radius).div_ceil(size) * size;
+    let mut padded = vec![identity; padded_length];
+    padded[radius..radius + length].copy_from_slice(line);
+
+    let mut forward = padded.clone();
+    let mut backward = padded.clone();
+    for block in (0..padded_length).step_by(size) {
+        for i in block + 1..block + size {
+            forward[i] = combine(forward[i - 1], padded[i]);
+        }
+        for i in (block..block + size - 1).rev() {
+            backward[i] = combine(backward[i + 1], padded[i]);
+        }
+    }
+
+    // The window for value i covers padded[i..i + size], which spans at most two blocks
+    (0..length).map(|i| {
+        let mut value = combine(backward[i], forward[i + size - 1]);
+        value[3] = line[i][3];
+        value
+    }).collect()
+}
+
+// Erosion or dilation of BGRA values
+fn apply(values: &[[f32; 4]], width: usize, height: usize, element: &Element, combine: Combine, identity: [f32; 4], reflect: bool) -> Vec<[f32; 4]> {
+    if element.rectangle {
+        // Rows then columns, each with the one-dimensional fast path
+        let mut result: Vec<[f32; 4]> = values.chunks(width.max(1)).flat_map(|row| van_herk(row, element.width / 2, combine, identity)).collect();
+        for x in 0..width {
+            let column: Vec<[f32; 4]> = (0..height).map(|y| result[y * width + x]).collect();
+            for (y, value) in van_herk(&column, element.height / 2, combine, identity).into_iter().enumerate() {
+                result[y * width + x] = value;
+            }
+        }
+        return result;
+    }
+
+    // Dilation uses the element mirrored through its center
+    let offsets: Vec<(isize, isize)> = element.offsets().into_iter().map(|(dx, dy)| if reflect { (-dx, -dy) } else { (dx, dy) }).collect();
+    (0..width * height).map(|i| {
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+        let mut value = identity;
+        for (dx, dy) in &offsets {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx >= 0 && ny >= 0 && (nx as usize) < width && (ny as usize) < height {
+                value = combine(value, values[ny as usize * width + nx as usize]);
+            }
+        }
+        value[3] = values[i][3];
+        value
+    }).collect()
+}
+
+fn erode_values(values: &[[f32; 4]], width: usize, height: usize, element: &Element) -> Vec<[f32; 4]> {
+    apply(values, width, height, element, minimum, [f32::INFINITY; 4], false)
+}
+
+fn dilate_values(values: &[[f32; 4]], width: usize, height: usize, element: &Element) -> Vec<[f32; 4]> {
+    apply(values, width, height, element, maximum, [f32::NEG_INFINITY; 4], true)
+}
+
+// Channel-wise difference of two sets of values, keeping the alpha of the first
+fn difference(a: &[[f32; 4]], b: &[[f32; 4]]) -> Vec<[f32; 4]> {
+    a.iter().zip(b.iter()).map(|(a, b)| [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3]]).collect()
+}
+
+// Applies a morphological operation, assuming the first row of the image is the top
+// Alpha is kept as it is
+pub fn morphology_image<P: Pixel>(image: &Image<P>, operation: Operation, element: &Element) -> Image<P> {
+    let (width, height) = (image.width(), image.height());
+    let values: Vec<[f32; 4]> = image.data.iter().map(|pixel| pixel.to_bgra()).collect();
+    let erode = |values: &[[f32; 4]]| erode_values(values, width, height, element);
+    let dilate = |values: &[[f32; 4]]| dilate_values(values, width, height, element);
+
+    let result = match operation {
+        Operation::Erode => erode(&values),
+        Operation::Dilate => dilate(&values),
+        Operation::Open => dilate(&erode(&values)),
+        Operation::Close => erode(&dilate(&values)),
+        Operation::Gradient => difference(&dilate(&values), &erode(&values)),
+        Operation::TopHat => difference(&values, &dilate(&erode(&values))),
+        Operation::BlackHat => difference(&erode(&dilate(&values)), &values),
+    };
+    Image::from_vec(width, height, result.into_iter().map(P::from_bgra).collect())
+}
+
+// Applies a morphological operation with the element laid out as displayed
+// TGA files normally store the bottom row first, so the element is turned upside down for those images
+pub fn morphology<P: Pixel>(image: &Data<P>, operation: Operation, element: &Element) -> Data<P> {
+    let pixels = if image.header.is_top_down() {
+        morphology_image(&image.pixels, operation, element)
+    }
+    else {
+        morphology_image(&image.pixels, operation, &element.flipped_vertically())
+    };
+    image.with_pixels(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    // Pixels set to 255 drawn as '#' and 0 as '.', one string per row
+    fn mask(rows: &[&str]) -> Image<Gray<u8>> {
+        let width = rows[0].len();
+        Image::from_fn(width, rows.len(), |x, y| Gray {value: if rows[y].as_bytes()[x] == b'#' { 255 } else { 0 }})
+    }
+
+    #[test]
+    fn test_element_shapes() {
+        let cross = Element::cross(3, 3);
+        assert_eq!(cross.mask, vec![false, true, false, true, true, true, false, true, false]);
+        let ellipse = Element::ellipse(5, 5);
+        assert!(!ellipse.mask[0] && ellipse.mask[2] && ellipse.mask[12]);
+        assert!(!ellipse.rectangle && Element::rectangle(3, 5).rectangle);
+
+        assert_eq!(Element::parse("Cross:3x3").unwrap(), cross);
+        assert!(Element::parse("ellipse:4x5").is_err());
+        assert!(Element::parse("star:3x3").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Element mask has no cells set")]
+    fn test_empty_element_panics() {
+        Element::custom(3, 3, vec![false; 9]);
+    }
+
+    #[test]
+    fn test_fast_rectangle_matches_mask() {
+        // Clearing the flag sends the same rectangle through the general code
+        let image = Image::from_fn(11, 8, |x, y| Gray {value: ((x * 41 + y * 73) % 256) as u8});
+        let values: Vec<[f32; 4]> = image.data.iter().map(|pixel| pixel.to_bgra()).collect();
+        let element = Element::rectangle(5, 3);
+        let mut general = element.clone();
+        general.rectangle = false;
+        assert_eq!(erode_values(&values, 11, 8, &element), erode_values(&values, 11, 8, &general));
+        assert_eq!(dilate_values(&values, 11, 8, &element), dilate_values(&values, 11, 8, &general));
+    }
+
+    #[test]
+    fn test_open_and_close_clean_masks() {
+        let speckled = mask(&[
+            "........",
+            ".####...",
+            ".####.#.",
+            ".####...",
+            "........",
+        ]);
+        let opened = morphology_image(&speckled, Operation::Open, &Element::rectangle(3, 3));
+        assert_eq!(opened, mask(&[
+            "........",
+            ".####...",
+            ".####...",
+            ".####...",
+            "........",
+        ]));
+
+        let holed = mask(&[
+            "######",
+            "##.###",
+            "######",
+        ]);
+        let closed = morphology_image(&holed, Operation::Close, &Element::cross(3, 3));
+        assert!(closed.data.iter().all(|pixel| pixel.value == 255));
+    }
+
+    #[test]
+    fn test_gradient_and_hats() {
+        let square = mask(&[
+            ".....",
+            ".###.",
+            ".###.",
+            ".###.",
+            ".....",
+        ]);
+        let element = Element::rectangle(3, 3);
+        let gradient = morphology_image(&square, Operation::Gradient, &element);
+        assert_eq!(gradient.get(2, 2).value, 0);
+        assert_eq!(gradient.get(1, 1).value, 255);
+        assert_eq!(gradient.get(0, 0).value, 255);
+
+        // The square survives opening, so the top-hat is empty; a lone dot is all top-hat
+        assert!(morphology_image(&square, Operation::TopHat, &element).data.iter().all(|pixel| pixel.value == 0));
+        let dot = mask(&["...", ".#.", "..."]);
+        assert_eq!(morphology_image(&dot, Operation::TopHat, &element), dot);
+
+        // A dark dot is all black-hat
+        let hole = mask(&["###", "#.#", "###"]);
+        assert_eq!(morphology_image(&hole, Operation::BlackHat, &element).get(1, 1).value, 255);
+    }
+}
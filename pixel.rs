@@ -0,0 +1,235 @@
+// Pixel layouts and the trait shared by all of them
+
+use std::fmt;
+
+use crate::channel::Channel;
+
+// Trait implemented by every pixel layout
+// Channels are indexed in storage order (blue, green, red, alpha for BGRA pixels)
+// and any alpha channel always comes after the color channels
+pub trait Pixel: Copy + PartialEq + fmt::Debug + fmt::Display {
+    // Type used to store each channel
+    type Channel: Channel;
+
+    // Total number of channels, including alpha
+    const CHANNELS: usize;
+
+    // Number of color channels, alpha is not counted here
+    const COLOR_CHANNELS: usize;
+
+    // Reads a channel by index
+    fn channel(&self, index: usize) -> Self::Channel;
+
+    // Builds a pixel by calling the function once for each channel index
+    fn from_fn(f: impl FnMut(usize) -> Self::Channel) -> Self;
+
+    // Converts the pixel to normalized BGRA (alpha is 1.0 for opaque layouts)
+    fn to_bgra(&self) -> [f32; 4];
+
+    // Builds a pixel from normalized BGRA
+    fn from_bgra(bgra: [f32; 4]) -> Self;
+
+    // Whether the layout stores an alpha channel
+    fn has_alpha() -> bool {
+        Self::CHANNELS > Self::COLOR_CHANNELS
+    }
+
+    // Converts to another pixel layout or channel type
+    fn convert<Q: Pixel>(&self) -> Q {
+        Q::from_bgra(self.to_bgra())
+    }
+}
+
+// Rec. 601 luma of normalized BGR values, used when color is reduced to one channel
+pub fn luma(blue: f32, green: f32, red: f32) -> f32 {
+    0.114 * blue + 0.587 * green + 0.299 * red
+}
+
+// Which values of a pixel an adjustment or filter changes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChannelMode {
+    // Blue, green and red each on their own
+    PerChannel,
+    // Only the luminance, shifting every channel by the same amount so colors don't bleed into each other
+    Luminance,
+}
+
+// Normalized BGRA values moved to a new luma, keeping alpha
+// Every color channel is shifted by the change in luma, so hues stay put
+pub fn with_luma(bgra: [f32; 4], target: f32) -> [f32; 4] {
+    let shift = target - luma(bgra[0], bgra[1], bgra[2]);
+    [bgra[0] + shift, bgra[1] + shift, bgra[2] + shift, bgra[3]]
+}
+
+// Struct representing a single BGR pixel, the layout of 24-bit TGA files
+// Channels are 8-bit by default, u16 and f32 storage keep extra precision between operations
+#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, Default)]
+pub struct Bgr<C: Channel = u8> {
+    pub blue: C,
+    pub green: C,
+    pub red: C,
+}
+
+impl<C: Channel> Pixel for Bgr<C> {
+    type Channel = C;
+    const CHANNELS: usize = 3;
+    const COLOR_CHANNELS: usize = 3;
+
+    fn channel(&self, index: usize) -> C {
+        match index {
+            0 => self.blue,
+            1 => self.green,
+            2 => self.red,
+            _ => panic!("BGR pixels have no channel {}", index),
+        }
+    }
+
+    fn from_fn(mut f: impl FnMut(usize) -> C) -> Self {
+        Bgr {blue: f(0), green: f(1), red: f(2)}
+    }
+
+    fn to_bgra(&self) -> [f32; 4] {
+        [self.blue.to_f32(), self.green.to_f32(), self.red.to_f32(), 1.0]
+    }
+
+    fn from_bgra(bgra: [f32; 4]) -> Self {
+        Bgr {blue: C::from_f32(bgra[0]), green: C::from_f32(bgra[1]), red: C::from_f32(bgra[2])}
+    }
+}
+
+// Struct representing a single BGRA pixel, the layout of 32-bit TGA files
+#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, Default)]
+pub struct Bgra<C: Channel = u8> {
+    pub blue: C,
+    pub green: C,
+    pub red: C,
+    pub alpha: C,
+}
+
+impl<C: Channel> Pixel for Bgra<C> {
+    type Channel = C;
+    const CHANNELS: usize = 4;
+    const COLOR_CHANNELS: usize = 3;
+
+    fn channel(&self, index: usize) -> C {
+        match index {
+            0 => self.blue,
+            1 => self.green,
+            2 => self.red,
+            3 => self.alpha,
+            _ => panic!("BGRA pixels have no channel {}", index),
+        }
+    }
+
+    fn from_fn(mut f: impl FnMut(usize) -> C) -> Self {
+        Bgra {blue: f(0), green: f(1), red: f(2), alpha: f(3)}
+    }
+
+    fn to_bgra(&self) -> [f32; 4] {
+        [self.blue.to_f32(), self.green.to_f32(), self.red.to_f32(), self.alpha.to_f32()]
+    }
+
+    fn from_bgra(bgra: [f32; 4]) -> Self {
+        Bgra {
+            blue: C::from_f32(bgra[0]),
+            green: C::from_f32(bgra[1]),
+            red: C::from_f32(bgra[2]),
+            alpha: C::from_f32(bgra[3]),
+        }
+    }
+}
+
+// Struct representing a single grayscale pixel
+// Converting color to gray uses luma, converting gray to color copies the value to every channel
+#[derive(PartialEq, Debug)]
+#[derive(Clone, Copy, Default)]
+pub struct Gray<C: Channel = u8> {
+    pub value: C,
+}
+
+impl<C: Channel> Pixel for Gray<C> {
+    type Channel = C;
+    const CHANNELS: usize = 1;
+    const COLOR_CHANNELS: usize = 1;
+
+    fn channel(&self, index: usize) -> C {
+        match index {
+            0 => self.value,
+            _ => panic!("Gray pixels have no channel {}", index),
+        }
+    }
+
+    fn from_fn(mut f: impl FnMut(usize) -> C) -> Self {
+        Gray {value: f(0)}
+    }
+
+    fn to_bgra(&self) -> [f32; 4] {
+        let value = self.value.to_f32();
+        [value, value, value, 1.0]
+    }
+
+    fn from_bgra(bgra: [f32; 4]) -> Self {
+        Gray {value: C::from_f32(luma(bgra[0], bgra[1], bgra[2]))}
+    }
+}
+
+// Test format for pixels
+impl<C: Channel> fmt::Display for Bgr<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pixel(R: {}, G: {}, B: {})", self.red, self.green, self.blue)
+    }
+}
+
+impl<C: Channel> fmt::Display for Bgra<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pixel(R: {}, G: {}, B: {}, A: {})", self.red, self.green, self.blue, self.alpha)
+    }
+}
+
+impl<C: Channel> fmt::Display for Gray<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pixel(V: {})", self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channels_by_index() {
+        // Channels are indexed in storage order
+        let pixel = Bgra {blue: 1u8, green: 2, red: 3, alpha: 4};
+        assert_eq!((0..4).map(|i| pixel.channel(i)).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(Bgra::from_fn(|i| pixel.channel(3 - i)), Bgra {blue: 4, green: 3, red: 2, alpha: 1});
+        assert!(Bgra::<u8>::has_alpha());
+        assert!(!Bgr::<u8>::has_alpha());
+    }
+
+    #[test]
+    fn test_with_luma_shifts_every_channel() {
+        let shifted = with_luma([0.1, 0.2, 0.3, 0.5], 0.5);
+        assert!((luma(shifted[0], shifted[1], shifted[2]) - 0.5).abs() < 1e-6);
+        assert!((shifted[2] - shifted[0] - 0.2).abs() < 1e-6);
+        assert_eq!(shifted[3], 0.5);
+    }
+
+    #[test]
+    fn test_layout_conversions() {
+        // Dropping and adding alpha keeps the color channels
+        let pixel = Bgr {blue: 10u8, green: 20, red: 30};
+        let with_alpha: Bgra<u8> = pixel.convert();
+        assert_eq!(with_alpha, Bgra {blue: 10, green: 20, red: 30, alpha: 255});
+        assert_eq!(with_alpha.convert::<Bgr<u8>>(), pixel);
+
+        // Changing channel type is lossless for 8-bit values
+        assert_eq!(pixel.convert::<Bgr<f32>>().convert::<Bgr<u16>>().convert::<Bgr<u8>>(), pixel);
+
+        // Gray pixels use luma and expand to every channel
+        let white: Gray<u8> = Bgr {blue: 255u8, green: 255, red: 255}.convert();
+        assert_eq!(white.value, 255);
+        assert_eq!(Gray {value: 7u8}.convert::<Bgr<u8>>(), Bgr {blue: 7, green: 7, red: 7});
+    }
+}
@@ -0,0 +1,235 @@
+// Rank-order filters (median, minimum, maximum and percentiles) over a square window
+// Each row keeps a histogram of the window that slides along with it (Huang's algorithm), so the
+// cost per pixel grows with the window's height rather than its area. Histograms have a bin for
+// each 8-bit value, so images holding finer values (from u16 or f32 channels) sort each window instead
+
+use std::io;
+
+use crate::channel::Channel;
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::resample::{self, Edge};
+use crate::Data;
+
+// Which value of the sorted window each pixel is replaced with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rank {
+    // The middle value, removes speckle noise while keeping edges sharp
+    Median,
+    // The darkest value, grows dark areas
+    Minimum,
+    // The brightest value, grows light areas
+    Maximum,
+    // The value below which the given fraction (0 to 1) of the window lies
+    Percentile(f32),
+}
+
+impl Rank {
+    // Rank named median, minimum or maximum, or percentile=<percent> (0 to 100), ignoring case
+    pub fn parse(text: &str) -> Result<Rank, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown rank: {}", text));
+        match text.trim().to_ascii_lowercase().as_str() {
+            "median" => Ok(Rank::Median),
+            "minimum" | "min" => Ok(Rank::Minimum),
+            "maximum" | "max" => Ok(Rank::Maximum),
+            other => match other.strip_prefix("percentile=").map(|percent| percent.trim().parse::<f32>()) {
+                Some(Ok(percent)) => Ok(Rank::Percentile(percent / 100.0)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+
+    // Position in the sorted window as a fraction of its length
+    fn fraction(self) -> f32 {
+        match self {
+            Rank::Median => 0.5,
+            Rank::Minimum => 0.0,
+            Rank::Maximum => 1.0,
+            Rank::Percentile(fraction) => fraction.clamp(0.0, 1.0),
+        }
+    }
+}
+
+// Histograms of the blue, green and red values inside the window
+struct Window {
+    histograms: [[u32; 256]; 3],
+    count: u32,
+}
+
+impl Window {
+    fn new() -> Window {
+        Window {histograms: [[0; 256]; 3], count: 0}
+    }
+
+    fn add(&mut self, levels: [u8; 4]) {
+        for (histogram, level) in self.histograms.iter_mut().zip(levels) {
+            histogram[level as usize] += 1;
+        }
+        self.count += 1;
+    }
+
+    fn remove(&mut self, levels: [u8; 4]) {
+        for (histogram, level) in self.histograms.iter_mut().zip(levels) {
+            histogram[level as usize] -= 1;
+        }
+        self.count -= 1;
+    }
+
+    // Level of each color channel at the given fraction of the way through the sorted window
+    fn select(&self, fraction: f32) -> [u8; 3] {
+        let target = (fraction * self.count.saturating_sub(1) as f32).round() as u32;
+        self.histograms.map(|histogram| {
+            let mut seen = 0;
+            for (level, count) in histogram.iter().enumerate() {
+                seen += count;
+                if seen > target {
+                    return level as u8;
+                }
+            }
+            255
+        })
+    }
+}
+
+// Replaces every color channel with the chosen rank of the (2 * radius + 1) square around it,
+// keeping alpha as it is; with Edge::Constant the window only covers pixels inside the image
+pub fn rank_filter_image<P: Pixel>(image: &Image<P>, radius: usize, rank: Rank, edge: Edge) -> Image<P> {
+    let (width, height) = (image.width(), image.height());
+
+    // Source column and row for every position the window can reach, None outside the image
+    let columns: Vec<Option<usize>> = (0..width + 2 * radius).map(|i| resample::edge_position(i as isize - radius as isize, width, edge)).collect();
+    let rows: Vec<Option<usize>> = (0..height + 2 * radius).map(|i| resample::edge_position(i as isize - radius as isize, height, edge)).collect();
+
+    let selected = if image.data.iter().all(|pixel| pixel.to_bgra()[..3].iter().all(|value| u8::from_f32(*value).to_f32() == *value)) {
+        histogram_ranks(image, &columns, &rows, 2 * radius + 1, rank.fraction())
+    }
+    else {
+        sorted_ranks(image, &columns, &rows, 2 * radius + 1, rank.fraction())
+    };
+    let data = selected.iter().zip(&image.data).map(|(selected, pixel)| {
+        P::from_bgra([selected[0], selected[1], selected[2], pixel.to_bgra()[3]])
+    }).collect();
+    Image::from_vec(width, height, data)
+}
+
+// Ranked color channels of every window, found with sliding histograms of 8-bit values
+fn histogram_ranks<P: Pixel>(image: &Image<P>, columns: &[Option<usize>], rows: &[Option<usize>], size: usize, fraction: f32) -> Vec<[f32; 3]> {
+    let (width, height) = (image.width(), image.height());
+    let levels: Vec<[u8; 4]> = image.data.iter().map(|pixel| pixel.to_bgra().map(u8::from_f32)).collect();
+
+    let mut selected = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let window_rows: Vec<usize> = rows[y..y + size].iter().flatten().copied().collect();
+
+        let mut window = Window::new();
+        for column in columns[..size].iter().flatten() {
+            window_rows.iter().for_each(|row| window.add(levels[row * width + column]));
+        }
+        for x in 0..width {
+            selected.push(window.select(fraction).map(u8::to_f32));
+
+            // Slide the window one pixel to the right
+            if x + 1 < width {
+                if let Some(column) = columns[x] {
+                    window_rows.iter().for_each(|row| window.remove(levels[row * width + column]));
+                }
+                if let Some(column) = columns[x + size] {
+                    window_rows.iter().for_each(|row| window.add(levels[row * width + column]));
+                }
+            }
+        }
+    }
+    selected
+}
+
+// Ranked color channels of every window, found by sorting the window's values at full precision
+fn sorted_ranks<P: Pixel>(image: &Image<P>, columns: &[Option<usize>], rows: &[Option<usize>], size: usize, fraction: f32) -> Vec<[f32; 3]> {
+    let (width, height) = (image.width(), image.height());
+    let values: Vec<[f32; 4]> = image.data.iter().map(|pixel| pixel.to_bgra()).collect();
+
+    let mut selected = Vec::with_capacity(width * height);
+    let mut window = Vec::with_capacity(size * size);
+    for y in 0..height {
+        let window_rows: Vec<usize> = rows[y..y + size].iter().flatten().copied().collect();
+        for x in 0..width {
+            let window_columns: Vec<usize> = columns[x..x + size].iter().flatten().copied().collect();
+            let target = (fraction * (window_rows.len() * window_columns.len()).saturating_sub(1) as f32).round() as usize;
+            selected.push([0, 1, 2].map(|channel| {
+                window.clear();
+                for row in &window_rows {
+                    window.extend(window_columns.iter().map(|column| values[row * width + column][channel]));
+                }
+                *window.select_nth_unstable_by(target, f32::total_cmp).1
+            }));
+        }
+    }
+    selected
+}
+
+// Rank filter of an image
+pub fn rank_filter<P: Pixel>(image: &Data<P>, radius: usize, rank: Rank, edge: Edge) -> Data<P> {
+    image.with_pixels(rank_filter_image(&image.pixels, radius, rank, edge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    fn pseudo_random(x: usize, y: usize) -> Gray<u8> {
+        Gray {value: ((x * 7919 + y * 104729) % 251) as u8}
+    }
+
+    #[test]
+    fn test_wide_channels_keep_their_precision() {
+        // Values 1/65535 apart, far closer than 8-bit steps, ranked without rounding
+        let image = Image::from_fn(5, 1, |x, _| Gray {value: 30000 + [4, 0, 3, 1, 2][x] as u16});
+        let filtered = rank_filter_image(&image, 1, Rank::Median, Edge::Clamp);
+        let values: Vec<u16> = filtered.data.iter().map(|pixel| pixel.value).collect();
+        assert_eq!(values, vec![30004, 30003, 30001, 30002, 30002]);
+        assert_eq!(rank_filter_image(&image, 2, Rank::Minimum, Edge::Constant).get(4, 0).value, 30001);
+
+        let image = image.convert::<Gray<f32>>();
+        assert_eq!(rank_filter_image(&image, 1, Rank::Maximum, Edge::Clamp).get(1, 0), image.get(0, 0));
+    }
+
+    #[test]
+    fn test_median_removes_specks() {
+        let mut image = Image::new(7, 7, Gray {value: 100u8});
+        image.put(2, 3, Gray {value: 255});
+        image.put(5, 1, Gray {value: 0});
+        let filtered = rank_filter_image(&image, 1, Rank::parse("median").unwrap(), Edge::Clamp);
+        assert!(filtered.data.iter().all(|pixel| pixel.value == 100));
+
+        assert_eq!(Rank::parse("Percentile=90").unwrap(), Rank::Percentile(0.9));
+        assert!(Rank::parse("mode").is_err());
+    }
+
+    #[test]
+    fn test_matches_sorting_the_window() {
+        let image = Image::from_fn(9, 7, pseudo_random);
+        let radius = 2;
+        for rank in [Rank::Median, Rank::Minimum, Rank::Maximum, Rank::Percentile(0.25)] {
+            for edge in [Edge::Mirror, Edge::Constant] {
+                let filtered = rank_filter_image(&image, radius, rank, edge);
+                for y in 0..7 {
+                    for x in 0..9 {
+                        let mut window: Vec<u8> = Vec::new();
+                        for dy in -2..=2isize {
+                            for dx in -2..=2isize {
+                                let column = resample::edge_position(x as isize + dx, 9, edge);
+                                let row = resample::edge_position(y as isize + dy, 7, edge);
+                                if let (Some(column), Some(row)) = (column, row) {
+                                    window.push(image.get(column, row).value);
+                                }
+                            }
+                        }
+                        window.sort();
+                        let expected = window[(rank.fraction() * (window.len() - 1) as f32).round() as usize];
+                        assert_eq!(filtered.get(x, y).value, expected, "{:?} {:?} at ({}, {})", rank, edge, x, y);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Interpolation shared by operations that read pixels at fractional positions
+
+use std::io;
+
+// How pixel values are estimated between pixel centers
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interpolation {
+    // Closest pixel, keeps hard edges
+    Nearest,
+    // Weighted average of the 2x2 surrounding pixels
+    Bilinear,
+    // Catmull-Rom cubic through the 4x4 surrounding pixels, sharper than bilinear
+    Bicubic,
+}
+
+impl Interpolation {
+    // Interpolation named nearest, bilinear or bicubic, ignoring case
+    pub fn parse(name: &str) -> Result<Interpolation, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Interpolation::Nearest),
+            "bilinear" => Ok(Interpolation::Bilinear),
+            "bicubic" => Ok(Interpolation::Bicubic),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown interpolation: {}", name))),
+        }
+    }
+}
+
+// How positions outside the image are read
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Edge {
+    // Repeat the nearest edge pixel
+    Clamp,
+    // Tile the image, so leaving the right edge comes back in on the left
+    Wrap,
+    // Reflect the image at its edges, repeating the edge pixel (cba|abc|cba)
+    Mirror,
+    // Read a fixed color chosen by the caller
+    Constant,
+}
+
+impl Edge {
+    // Edge mode named clamp, wrap, mirror or constant, ignoring case
+    pub fn parse(name: &str) -> Result<Edge, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(Edge::Clamp),
+            "wrap" => Ok(Edge::Wrap),
+            "mirror" => Ok(Edge::Mirror),
+            "constant" => Ok(Edge::Constant),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown edge mode: {}", name))),
+        }
+    }
+}
+
+// Position inside 0..length that an outside position reads from, None for Edge::Constant
+pub fn edge_position(position: isize, length: usize, edge: Edge) -> Option<usize> {
+    let length = length as isize;
+    if (0..length).contains(&position) {
+        return Some(position as usize);
+    }
+    if length == 0 {
+        return None;
+    }
+    match edge {
+        Edge::Clamp => Some(position.clamp(0, length - 1) as usize),
+        Edge::Wrap => Some(position.rem_euclid(length) as usize),
+        Edge::Mirror => {
+            let period = position.rem_euclid(2 * length);
+            Some(if period < length { period } else { 2 * length - 1 - period } as usize)
+        }
+        Edge::Constant => None,
+    }
+}
+
+// Mitchell-Netravali family of cubic kernels, evaluated at distance x from the sample
+// b = 0, c = 0.5 is Catmull-Rom and b = c = 1/3 is the Mitchell filter
+pub fn cubic(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+    }
+    else if x < 2.0 {
+        ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+    }
+    else {
+        0.0
+    }
+}
+
+// Color channels multiplied by alpha, so transparent pixels don't bleed their color when averaged
+pub fn premultiply(bgra: [f32; 4]) -> [f32; 4] {
+    [bgra[0] * bgra[3], bgra[1] * bgra[3], bgra[2] * bgra[3], bgra[3]]
+}
+
+// Undoes premultiply, fully transparent pixels come back black
+pub fn unpremultiply(bgra: [f32; 4]) -> [f32; 4] {
+    if bgra[3] <= 0.0 {
+        return [0.0; 4];
+    }
+    [bgra[0] / bgra[3], bgra[1] / bgra[3], bgra[2] / bgra[3], bgra[3]]
+}
+
+// Estimates the value at (x, y), where pixel centers sit on whole numbers
+// `fetch` returns the premultiplied BGRA value of any pixel position, including ones outside the image
+pub fn sample(x: f32, y: f32, interpolation: Interpolation, fetch: impl Fn(isize, isize) -> [f32; 4]) -> [f32; 4] {
+    match interpolation {
+        Interpolation::Nearest => fetch(x.round() as isize, y.round() as isize),
+        Interpolation::Bilinear => {
+            let left = x.floor();
+            let top = y.floor();
+            let (fx, fy) = (x - left, y - top);
+            let (left, top) = (left as isize, top as isize);
+            let weights = [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)];
+
+            let mut result = [0.0; 4];
+            for (dx, dy, weight) in weights {
+                let value = fetch(left + dx, top + dy);
+                for i in 0..4 {
+                    result[i] += value[i] * weight;
+                }
+            }
+            result
+        }
+        Interpolation::Bicubic => {
+            let left = x.floor();
+            let top = y.floor();
+            let (fx, fy) = (x - left, y - top);
+            let (left, top) = (left as isize, top as isize);
+
+            // Catmull-Rom weights of the four columns and rows around the sample
+            let column_weights: Vec<f32> = (-1..3).map(|i| cubic(fx - i as f32, 0.0, 0.5)).collect();
+            let row_weights: Vec<f32> = (-1..3).map(|i| cubic(fy - i as f32, 0.0, 0.5)).collect();
+
+            let mut result = [0.0; 4];
+            for (row, row_weight) in row_weights.iter().enumerate() {
+                for (column, column_weight) in column_weights.iter().enumerate() {
+                    let value = fetch(left + column as isize - 1, top + row as isize - 1);
+                    for i in 0..4 {
+                        result[i] += value[i] * row_weight * column_weight;
+                    }
+                }
+            }
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Horizontal ramp where each pixel's value is its x position
+    fn ramp(x: isize, _y: isize) -> [f32; 4] {
+        [x as f32, x as f32, x as f32, 1.0]
+    }
+
+    #[test]
+    fn test_cubic_kernels() {
+        // Catmull-Rom passes through the samples
+        assert_eq!(cubic(0.0, 0.0, 0.5), 1.0);
+        assert_eq!(cubic(1.0, 0.0, 0.5), 0.0);
+        assert_eq!(cubic(2.5, 0.0, 0.5), 0.0);
+
+        // Weights of any cubic in the family add up to one
+        for offset in [0.0, 0.25, 0.5, 0.9] {
+            let total: f32 = (-1..3).map(|i| cubic(offset - i as f32, 1.0 / 3.0, 1.0 / 3.0)).sum();
+            assert!((total - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_interpolation_of_ramp() {
+        // Linear data is reproduced exactly by bilinear and bicubic interpolation
+        assert_eq!(sample(2.25, 0.0, Interpolation::Nearest, ramp)[0], 2.0);
+        assert!((sample(2.25, 0.7, Interpolation::Bilinear, ramp)[0] - 2.25).abs() < 1e-5);
+        assert!((sample(2.25, 0.7, Interpolation::Bicubic, ramp)[0] - 2.25).abs() < 1e-5);
+        assert_eq!(Interpolation::parse("Bicubic").unwrap(), Interpolation::Bicubic);
+        assert!(Interpolation::parse("cubic").is_err());
+    }
+
+    #[test]
+    fn test_edge_positions() {
+        let positions = |edge| (-4..7).map(|i| edge_position(i, 3, edge)).collect::<Vec<_>>();
+        let all = |values: [usize; 11]| values.iter().map(|value| Some(*value)).collect::<Vec<_>>();
+        assert_eq!(positions(Edge::Clamp), all([0, 0, 0, 0, 0, 1, 2, 2, 2, 2, 2]));
+        assert_eq!(positions(Edge::Wrap), all([2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0]));
+        assert_eq!(positions(Edge::Mirror), all([2, 2, 1, 0, 0, 1, 2, 2, 1, 0, 0]));
+        assert_eq!(edge_position(-1, 3, Edge::Constant), None);
+        assert_eq!(edge_position(1, 3, Edge::Constant), Some(1));
+    }
+
+    #[test]
+    fn test_premultiplied_alpha() {
+        let color = [1.0, 0.5, 0.0, 0.5];
+        assert_eq!(premultiply(color), [0.5, 0.25, 0.0, 0.5]);
+        assert_eq!(unpremultiply(premultiply(color)), color);
+        assert_eq!(unpremultiply([0.3, 0.3, 0.3, 0.0]), [0.0; 4]);
+    }
+}
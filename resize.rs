@@ -0,0 +1,278 @@
+// Image resizing with separable resampling filters
+// Each axis is resampled in its own pass, and when shrinking the filter is widened by the
+// scale factor so every source pixel contributes (otherwise fine detail aliases)
+
+use std::f32::consts::PI;
+use std::io;
+
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::resample::{self, cubic};
+use crate::Data;
+
+// Resampling filters, from fastest to sharpest
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    // Closest pixel, no smoothing
+    Nearest,
+    // Triangle filter, linear interpolation when enlarging
+    Bilinear,
+    // Cubic with B = 0, C = 0.5, sharp with slight ringing
+    CatmullRom,
+    // Cubic with B = C = 1/3, softer with less ringing
+    Mitchell,
+    // Windowed sinc over three lobes, sharpest
+    Lanczos3,
+}
+
+impl Filter {
+    // Filter named nearest, bilinear, catmull-rom, mitchell or lanczos3, ignoring case
+    pub fn parse(name: &str) -> Result<Filter, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Filter::Nearest),
+            "bilinear" => Ok(Filter::Bilinear),
+            "catmull-rom" | "bicubic" => Ok(Filter::CatmullRom),
+            "mitchell" => Ok(Filter::Mitchell),
+            "lanczos3" | "lanczos" => Ok(Filter::Lanczos3),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown resize filter: {}", name))),
+        }
+    }
+
+    // Distance from the center beyond which the filter is zero
+    fn support(self) -> f32 {
+        match self {
+            Filter::Nearest => 0.5,
+            Filter::Bilinear => 1.0,
+            Filter::CatmullRom | Filter::Mitchell => 2.0,
+            Filter::Lanczos3 => 3.0,
+        }
+    }
+
+    // Filter value at distance x from the center
+    fn weight(self, x: f32) -> f32 {
+        match self {
+            Filter::Nearest => if (-0.5..0.5).contains(&x) { 1.0 } else { 0.0 },
+            Filter::Bilinear => (1.0 - x.abs()).max(0.0),
+            Filter::CatmullRom => cubic(x, 0.0, 0.5),
+            Filter::Mitchell => cubic(x, 1.0 / 3.0, 1.0 / 3.0),
+            Filter::Lanczos3 => {
+                if x.abs() >= 3.0 {
+                    0.0
+                }
+                else {
+                    sinc(x) * sinc(x / 3.0)
+                }
+            }
+        }
+    }
+}
+
+// Normalized sinc function
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    }
+    else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// How the output size is chosen
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Size {
+    // Exactly this width and height
+    Exact(usize, usize),
+    // Both sides multiplied by a factor
+    Factor(f32),
+    // Largest size within the bounds that keeps the aspect ratio
+    Fit(usize, usize),
+}
+
+impl Size {
+    // Size written as <width>x<height>, fit:<width>x<height> or a factor such as 0.5
+    pub fn parse(text: &str) -> Result<Size, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid size: {}", text));
+        let dimensions = |text: &str| -> Result<(usize, usize), io::Error> {
+            let (width, height) = text.split_once('x').ok_or_else(invalid)?;
+            match (width.trim().parse(), height.trim().parse()) {
+                (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+                _ => Err(invalid()),
+            }
+        };
+
+        if let Some(bounds) = text.strip_prefix("fit:") {
+            let (width, height) = dimensions(bounds)?;
+            Ok(Size::Fit(width, height))
+        }
+        else if text.contains('x') {
+            let (width, height) = dimensions(text)?;
+            Ok(Size::Exact(width, height))
+        }
+        else {
+            match text.trim().parse::<f32>() {
+                Ok(factor) if factor > 0.0 => Ok(Size::Factor(factor)),
+                _ => Err(invalid()),
+            }
+        }
+    }
+}
+
+// Output width and height for a requested size, never smaller than one pixel
+pub fn target_size(width: usize, height: usize, size: Size) -> (usize, usize) {
+    let scaled = |length: usize, factor: f32| ((length as f32 * factor).round() as usize).max(1);
+    match size {
+        Size::Exact(new_width, new_height) => (new_width, new_height),
+        Size::Factor(factor) => (scaled(width, factor), scaled(height, factor)),
+        Size::Fit(max_width, max_height) => {
+            let factor = (max_width as f32 / width as f32).min(max_height as f32 / height as f32);
+            (scaled(width, factor).min(max_width), scaled(height, factor).min(max_height))
+        }
+    }
+}
+
+// Source pixels and normalized weights that make up one output pixel
+struct Contribution {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+// Contributions for every output position along one axis
+fn contributions(source_length: usize, target_length: usize, filter: Filter) -> Vec<Contribution> {
+    let scale = target_length as f32 / source_length as f32;
+
+    // Widen the filter when shrinking so it covers all the source pixels being merged
+    let filter_scale = if filter == Filter::Nearest { 1.0 } else { (1.0 / scale).max(1.0) };
+    let support = filter.support() * filter_scale;
+
+    (0..target_length).map(|i| {
+        // Position of the output pixel's center in source pixel coordinates
+        let center = (i as f32 + 0.5) / scale - 0.5;
+        let start = (center - support).ceil().max(0.0) as usize;
+        let end = ((center + support).floor() as usize).min(source_length - 1);
+
+        let mut weights: Vec<f32> = (start..=end).map(|j| filter.weight((j as f32 - center) / filter_scale)).collect();
+        let total: f32 = weights.iter().sum();
+        if total != 0.0 {
+            weights.iter_mut().for_each(|weight| *weight /= total);
+        }
+        else {
+            // Nothing in range (only possible at the very edge), use the nearest source pixel
+            let nearest = (center.round().max(0.0) as usize).min(source_length - 1);
+            return Contribution {start: nearest, weights: vec![1.0]};
+        }
+        Contribution {start, weights}
+    }).collect()
+}
+
+// Resizes an image to exactly new_width x new_height
+pub fn resize_image<P: Pixel>(image: &Image<P>, new_width: usize, new_height: usize, filter: Filter) -> Image<P> {
+    let width = image.width();
+    let height = image.height();
+    if width == 0 || height == 0 || new_width == 0 || new_height == 0 {
+        return Image::from_vec(new_width, new_height, vec![P::from_bgra([0.0; 4]); new_width * new_height]);
+    }
+
+    // Work on premultiplied values so transparent pixels don't bleed their color
+    let source: Vec<[f32; 4]> = image.data.iter().map(|pixel| resample::premultiply(pixel.to_bgra())).collect();
+
+    // Horizontal pass: width changes, height stays the same
+    let columns = contributions(width, new_width, filter);
+    let mut horizontal = vec![[0.0f32; 4]; new_width * height];
+    for y in 0..height {
+        let row = &source[y * width..(y + 1) * width];
+        for (x, contribution) in columns.iter().enumerate() {
+            let value = &mut horizontal[y * new_width + x];
+            for (offset, weight) in contribution.weights.iter().enumerate() {
+                let pixel = row[contribution.start + offset];
+                for c in 0..4 {
+                    value[c] += pixel[c] * weight;
+                }
+            }
+        }
+    }
+
+    // Vertical pass: height changes
+    let rows = contributions(height, new_height, filter);
+    let mut data = Vec::with_capacity(new_width * new_height);
+    for contribution in &rows {
+        for x in 0..new_width {
+            let mut value = [0.0f32; 4];
+            for (offset, weight) in contribution.weights.iter().enumerate() {
+                let pixel = horizontal[(contribution.start + offset) * new_width + x];
+                for c in 0..4 {
+                    value[c] += pixel[c] * weight;
+                }
+            }
+            data.push(P::from_bgra(resample::unpremultiply(value)));
+        }
+    }
+
+    Image::from_vec(new_width, new_height, data)
+}
+
+// Resizes an image, updating the header's width and height
+pub fn resize<P: Pixel>(image: &Data<P>, size: Size, filter: Filter) -> Data<P> {
+    let (new_width, new_height) = target_size(image.pixels.width(), image.pixels.height(), size);
+    image.with_pixels(resize_image(&image.pixels, new_width, new_height, filter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    const FILTERS: [Filter; 5] = [Filter::Nearest, Filter::Bilinear, Filter::CatmullRom, Filter::Mitchell, Filter::Lanczos3];
+
+    #[test]
+    fn test_target_sizes() {
+        assert_eq!(target_size(640, 480, Size::Exact(10, 20)), (10, 20));
+        assert_eq!(target_size(640, 480, Size::Factor(0.5)), (320, 240));
+        assert_eq!(target_size(640, 480, Size::Fit(100, 100)), (100, 75));
+        assert_eq!(target_size(480, 640, Size::Fit(100, 100)), (75, 100));
+        assert_eq!(target_size(3, 3, Size::Factor(0.01)), (1, 1));
+
+        assert_eq!(Size::parse("640x480").unwrap(), Size::Exact(640, 480));
+        assert_eq!(Size::parse("fit:100x50").unwrap(), Size::Fit(100, 50));
+        assert_eq!(Size::parse("0.5").unwrap(), Size::Factor(0.5));
+        assert!(Size::parse("-2").is_err() && Size::parse("10x").is_err() && Size::parse("0x5").is_err());
+        assert_eq!(Filter::parse("Lanczos3").unwrap(), Filter::Lanczos3);
+    }
+
+    #[test]
+    fn test_flat_image_stays_flat() {
+        // Normalized weights keep a single color unchanged with every filter
+        let image = Image::new(13, 7, Gray {value: 90u8});
+        for filter in FILTERS {
+            for (width, height) in [(5, 3), (29, 17), (13, 7)] {
+                let resized = resize_image(&image, width, height, filter);
+                assert!(resized.data.iter().all(|pixel| pixel.value == 90), "{:?} to {}x{}", filter, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_size_is_unchanged() {
+        // Mitchell is left out because it smooths slightly even at the original size
+        let image = Image::from_fn(9, 4, |x, y| Gray {value: (x * 25 + y * 3) as u8});
+        for filter in [Filter::Nearest, Filter::Bilinear, Filter::CatmullRom, Filter::Lanczos3] {
+            assert_eq!(resize_image(&image, 9, 4, filter), image, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_downscaling_averages_detail() {
+        // Shrinking a one-pixel checkerboard to a quarter of its size gives gray, not a pattern
+        let checkerboard = Image::from_fn(64, 64, |x, y| Gray {value: if (x + y) % 2 == 0 { 255u8 } else { 0 }});
+        for filter in [Filter::Bilinear, Filter::CatmullRom, Filter::Mitchell, Filter::Lanczos3] {
+            let resized = resize_image(&checkerboard, 16, 16, filter);
+            assert!(resized.data.iter().all(|pixel| pixel.value.abs_diff(128) <= 2), "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn test_nearest_upscale_repeats_pixels() {
+        let image = Image::from_vec(2, 1, vec![Gray {value: 10u8}, Gray {value: 20}]);
+        let resized = resize_image(&image, 4, 2, Filter::Nearest);
+        assert_eq!(resized.data.iter().map(|pixel| pixel.value).collect::<Vec<_>>(), vec![10, 10, 20, 20, 10, 10, 20, 20]);
+    }
+}
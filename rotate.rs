@@ -0,0 +1,144 @@
+// Rotation by any angle, resampling the source with the chosen interpolation
+
+use crate::geometry::Orientation;
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::resample::{self, Interpolation};
+use crate::Data;
+
+// What happens to the canvas when the rotated image no longer fits it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Canvas {
+    // Grow the canvas so the whole rotated image is kept
+    Expand,
+    // Keep the original size, cutting off the rotated corners
+    Crop,
+}
+
+// Lossless transform for angles that are a multiple of 90 degrees, if there is one
+fn quarter_turn(degrees: f32) -> Option<Orientation> {
+    let turns = degrees / 90.0;
+    if turns.fract() != 0.0 {
+        return None;
+    }
+    match (turns as i64).rem_euclid(4) {
+        1 => Some(Orientation::Rotate90),
+        2 => Some(Orientation::Rotate180),
+        3 => Some(Orientation::Rotate270),
+        _ => None,
+    }
+}
+
+// Rotates an image clockwise, assuming its first row is the top of the picture
+// Corners uncovered by the rotated image are set to the background; use a transparent
+// background with an alpha pixel layout to leave them transparent
+pub fn rotate_image<P: Pixel>(image: &Image<P>, degrees: f32, interpolation: Interpolation, canvas: Canvas, background: P) -> Image<P> {
+    let width = image.width();
+    let height = image.height();
+
+    // Multiples of 90 degrees don't need resampling
+    if degrees % 360.0 == 0.0 {
+        return image.clone();
+    }
+    if let Some(orientation) = quarter_turn(degrees) {
+        if canvas == Canvas::Expand || orientation == Orientation::Rotate180 || width == height {
+            return image.reorient(orientation);
+        }
+    }
+
+    let (sin, cos) = degrees.to_radians().sin_cos();
+
+    // Size of the bounding box around the rotated image (small errors are ignored so exact fits don't grow)
+    let (new_width, new_height) = match canvas {
+        Canvas::Expand => (
+            (width as f32 * cos.abs() + height as f32 * sin.abs() - 1e-3).ceil().max(0.0) as usize,
+            (width as f32 * sin.abs() + height as f32 * cos.abs() - 1e-3).ceil().max(0.0) as usize,
+        ),
+        Canvas::Crop => (width, height),
+    };
+
+    // Pixel centers sit on whole numbers, so the middle of the image is at (size - 1) / 2
+    let source_center_x = (width as f32 - 1.0) / 2.0;
+    let source_center_y = (height as f32 - 1.0) / 2.0;
+    let center_x = (new_width as f32 - 1.0) / 2.0;
+    let center_y = (new_height as f32 - 1.0) / 2.0;
+
+    // Pixels outside the source read as the background
+    let outside = resample::premultiply(background.to_bgra());
+    let fetch = |x: isize, y: isize| {
+        if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+            outside
+        }
+        else {
+            resample::premultiply(image.get(x as usize, y as usize).to_bgra())
+        }
+    };
+
+    // Rotate each destination position backwards to find where it comes from in the source
+    Image::from_fn(new_width, new_height, |x, y| {
+        let dx = x as f32 - center_x;
+        let dy = y as f32 - center_y;
+        let source_x = source_center_x + cos * dx + sin * dy;
+        let source_y = source_center_y - sin * dx + cos * dy;
+        P::from_bgra(resample::unpremultiply(resample::sample(source_x, source_y, interpolation, fetch)))
+    })
+}
+
+// Rotates an image clockwise as it is displayed, updating the header's width and height
+// TGA files normally store the bottom row first, so the angle is reversed for those images
+pub fn rotate<P: Pixel>(image: &Data<P>, degrees: f32, interpolation: Interpolation, canvas: Canvas, background: P) -> Data<P> {
+    let degrees = if image.header.is_top_down() { degrees } else { -degrees };
+    image.with_pixels(rotate_image(&image.pixels, degrees, interpolation, canvas, background))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::{Bgra, Gray};
+
+    #[test]
+    fn test_quarter_turns_are_lossless() {
+        let image = Image::from_fn(5, 3, |x, y| Gray {value: (y * 5 + x) as u8});
+        let background = Gray {value: 0};
+        assert_eq!(rotate_image(&image, 90.0, Interpolation::Bicubic, Canvas::Expand, background), image.rotate_90());
+        assert_eq!(rotate_image(&image, -90.0, Interpolation::Bilinear, Canvas::Expand, background), image.rotate_270());
+        assert_eq!(rotate_image(&image, 540.0, Interpolation::Nearest, Canvas::Crop, background), image.rotate_180());
+    }
+
+    #[test]
+    fn test_expand_fits_rotated_image() {
+        // A 45 degree turn of a 10x10 square needs a canvas about 14.14 pixels wide
+        let image = Image::new(10, 10, Gray {value: 200u8});
+        let rotated = rotate_image(&image, 45.0, Interpolation::Bilinear, Canvas::Expand, Gray {value: 0});
+        assert_eq!((rotated.width(), rotated.height()), (15, 15));
+
+        // The middle keeps the image's color and the corners get the background
+        assert_eq!(rotated.get(7, 7).value, 200);
+        assert_eq!(rotated.get(0, 0).value, 0);
+
+        // Cropping keeps the original size
+        let cropped = rotate_image(&image, 45.0, Interpolation::Bilinear, Canvas::Crop, Gray {value: 0});
+        assert_eq!((cropped.width(), cropped.height()), (10, 10));
+    }
+
+    #[test]
+    fn test_transparent_corners() {
+        let image = Image::new(8, 8, Bgra {blue: 0u8, green: 0, red: 255, alpha: 255});
+        let clear = Bgra {blue: 0, green: 0, red: 0, alpha: 0};
+        let rotated = rotate_image(&image, 30.0, Interpolation::Bicubic, Canvas::Expand, clear);
+
+        // Corners are transparent and edge pixels keep their color instead of darkening
+        assert_eq!(rotated.get(0, 0).alpha, 0);
+        let edge = rotated.data.iter().find(|pixel| pixel.alpha > 20 && pixel.alpha < 235).unwrap();
+        assert!(edge.red >= 250);
+    }
+
+    #[test]
+    fn test_rotation_direction() {
+        // Bright pixel left of center moves above center when turned clockwise
+        let mut image = Image::new(5, 5, Gray {value: 0u8});
+        image.put(0, 2, Gray {value: 255});
+        let rotated = rotate_image(&image, 90.0 + 1e-3, Interpolation::Nearest, Canvas::Crop, Gray {value: 0});
+        assert_eq!(rotated.get(2, 0).value, 255);
+    }
+}
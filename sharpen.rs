@@ -0,0 +1,111 @@
+// Sharpening filters: unsharp mask, Laplacian sharpen and high-pass
+
+use crate::blur;
+use crate::convolve::{self, Convolution, Kernel};
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::resample::Edge;
+use crate::Data;
+
+// Adds back the difference between the image and a blurred copy, scaled by amount
+// Radius is the blur's sigma in pixels; channels that differ from the blur by less than
+// the threshold (a fraction of full intensity) are left alone so flat areas don't turn grainy
+pub fn unsharp_mask_image<P: Pixel>(image: &Image<P>, amount: f32, radius: f32, threshold: f32, edge: Edge) -> Image<P> {
+    let blurred = blur::gaussian_blur_image(image, radius, edge);
+    image.zip_map(&blurred, |pixel, blurred_pixel| {
+        let original = pixel.to_bgra();
+        let blurred = blurred_pixel.to_bgra();
+        let mut sharpened = original;
+        for c in 0..3 {
+            let difference = original[c] - blurred[c];
+            if difference.abs() >= threshold {
+                sharpened[c] = original[c] + amount * difference;
+            }
+        }
+        P::from_bgra(sharpened)
+    })
+}
+
+// Subtracts the Laplacian (the difference from the four direct neighbours), scaled by strength
+pub fn laplacian_sharpen_image<P: Pixel>(image: &Image<P>, strength: f32, edge: Edge) -> Image<P> {
+    let kernel = Kernel::new(3, 3, vec![
+        0.0, -strength, 0.0,
+        -strength, 1.0 + 4.0 * strength, -strength,
+        0.0, -strength, 0.0,
+    ]);
+    convolve::convolve_image(image, &kernel, &Convolution::new(edge))
+}
+
+// Fine detail only: the image minus a blurred copy, centered on middle gray
+// Overlaying the result on the original (overlay_blend with this as the top image) sharpens it
+pub fn high_pass_image<P: Pixel>(image: &Image<P>, radius: f32, edge: Edge) -> Image<P> {
+    let blurred = blur::gaussian_blur_image(image, radius, edge);
+    image.zip_map(&blurred, |pixel, blurred_pixel| {
+        let original = pixel.to_bgra();
+        let blurred = blurred_pixel.to_bgra();
+        P::from_bgra([
+            original[0] - blurred[0] + 0.5,
+            original[1] - blurred[1] + 0.5,
+            original[2] - blurred[2] + 0.5,
+            original[3],
+        ])
+    })
+}
+
+// Unsharp mask of an image
+pub fn unsharp_mask<P: Pixel>(image: &Data<P>, amount: f32, radius: f32, threshold: f32, edge: Edge) -> Data<P> {
+    image.with_pixels(unsharp_mask_image(&image.pixels, amount, radius, threshold, edge))
+}
+
+// Laplacian sharpen of an image
+pub fn laplacian_sharpen<P: Pixel>(image: &Data<P>, strength: f32, edge: Edge) -> Data<P> {
+    image.with_pixels(laplacian_sharpen_image(&image.pixels, strength, edge))
+}
+
+// High-pass of an image, ready to be overlaid on the original
+pub fn high_pass<P: Pixel>(image: &Data<P>, radius: f32, edge: Edge) -> Data<P> {
+    image.with_pixels(high_pass_image(&image.pixels, radius, edge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    // Dark left half and light right half
+    fn step() -> Image<Gray<u8>> {
+        Image::from_fn(12, 3, |x, _| Gray {value: if x < 6 { 80 } else { 160 }})
+    }
+
+    #[test]
+    fn test_unsharp_mask_boosts_edges() {
+        let sharpened = unsharp_mask_image(&step(), 1.0, 1.0, 0.0, Edge::Clamp);
+        assert!(sharpened.get(5, 1).value < 80);
+        assert!(sharpened.get(6, 1).value > 160);
+        assert_eq!(sharpened.get(0, 1).value, 80);
+
+        // A threshold above the edge contrast leaves the image alone
+        assert_eq!(unsharp_mask_image(&step(), 1.0, 1.0, 0.5, Edge::Clamp), step());
+    }
+
+    #[test]
+    fn test_laplacian_sharpen() {
+        let sharpened = laplacian_sharpen_image(&step(), 1.0, Edge::Clamp);
+        assert_eq!(sharpened.get(5, 1).value, 0);
+        assert_eq!(sharpened.get(6, 1).value, 240);
+        assert_eq!(sharpened.get(2, 1).value, 80);
+    }
+
+    #[test]
+    fn test_high_pass_overlay_sharpens() {
+        let image = step();
+        let detail = high_pass_image(&image, 1.0, Edge::Clamp);
+
+        // Flat areas become middle gray, which overlay leaves unchanged
+        assert_eq!(detail.get(0, 1).value, 128);
+        let sharpened = crate::overlay_blend(&detail, &image);
+        assert_eq!(sharpened.get(0, 1).value, 80);
+        assert!(sharpened.get(5, 1).value < 80);
+        assert!(sharpened.get(6, 1).value > 160);
+    }
+}
@@ -0,0 +1,204 @@
+// Edge-preserving smoothing: bilateral and guided filters
+// Both smooth flat areas like a blur but stop at edges, by weighting neighbours on how
+// similar they are (bilateral) or by following a guide image (guided)
+
+use crate::blur;
+use crate::image::Image;
+use crate::pixel::{self, ChannelMode, Pixel};
+use crate::resample::{self, Edge};
+use crate::Data;
+
+// Runs a filter over each color channel (or the luminance) of an image, keeping alpha
+// The filter gets a plane of normalized values in row-major order and returns the filtered plane
+fn filter_planes<P: Pixel>(image: &Image<P>, mode: ChannelMode, filter: impl Fn(&[f32]) -> Vec<f32>) -> Image<P> {
+    let values: Vec<[f32; 4]> = image.data.iter().map(|pixel| pixel.to_bgra()).collect();
+    let mut filtered = values.clone();
+    match mode {
+        ChannelMode::PerChannel => {
+            for c in 0..3 {
+                let plane: Vec<f32> = values.iter().map(|value| value[c]).collect();
+                for (value, result) in filtered.iter_mut().zip(filter(&plane)) {
+                    value[c] = result;
+                }
+            }
+        }
+        ChannelMode::Luminance => {
+            let plane: Vec<f32> = values.iter().map(|value| pixel::luma(value[0], value[1], value[2])).collect();
+            for (value, result) in filtered.iter_mut().zip(filter(&plane)) {
+                *value = pixel::with_luma(*value, result);
+            }
+        }
+    }
+    Image::from_vec(image.width(), image.height(), filtered.into_iter().map(P::from_bgra).collect())
+}
+
+// Bilateral filter of one plane
+fn bilateral_plane(plane: &[f32], width: usize, height: usize, spatial_sigma: f32, range_sigma: f32, edge: Edge) -> Vec<f32> {
+    let radius = (2.0 * spatial_sigma).ceil().max(1.0) as isize;
+    let size = (2 * radius + 1) as usize;
+
+    // Weights for distance, the same for every pixel
+    let spatial: Vec<f32> = (-radius..=radius).flat_map(|dy| (-radius..=radius).map(move |dx| {
+        (-((dx * dx + dy * dy) as f32) / (2.0 * spatial_sigma * spatial_sigma)).exp()
+    })).collect();
+    let range_scale = -1.0 / (2.0 * range_sigma * range_sigma);
+
+    (0..width * height).map(|i| {
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+        let center = plane[i];
+        let (mut total, mut weights) = (0.0, 0.0);
+        for dy in -radius..=radius {
+            let Some(row) = resample::edge_position(y + dy, height, edge) else { continue };
+            for dx in -radius..=radius {
+                let Some(column) = resample::edge_position(x + dx, width, edge) else { continue };
+                let value = plane[row * width + column];
+                let difference = value - center;
+                let weight = spatial[(dy + radius) as usize * size + (dx + radius) as usize] * (difference * difference * range_scale).exp();
+                total += value * weight;
+                weights += weight;
+            }
+        }
+        total / weights
+    }).collect()
+}
+
+// Bilateral filter: a Gaussian blur of the given spatial sigma (in pixels) in which neighbours count
+// less the more their value differs, by a Gaussian of the range sigma (a fraction of full intensity)
+pub fn bilateral_filter_image<P: Pixel>(image: &Image<P>, spatial_sigma: f32, range_sigma: f32, mode: ChannelMode, edge: Edge) -> Image<P> {
+    if spatial_sigma <= 0.0 || range_sigma <= 0.0 {
+        return image.clone();
+    }
+    let (width, height) = (image.width(), image.height());
+    filter_planes(image, mode, |plane| bilateral_plane(plane, width, height, spatial_sigma, range_sigma, edge))
+}
+
+// Guided filter of one plane: locally the result is a linear function of the guide, fitted to the
+// input over every (2 * radius + 1) window; epsilon keeps the fit from following weak guide detail
+fn guided_plane(plane: &[f32], guide: &[f32], width: usize, height: usize, radius: usize, epsilon: f32, edge: Edge) -> Vec<f32> {
+    // With Edge::Constant the box blur counts pixels outside the image as zero, so each window sum is
+    // divided by the share of the window inside the image to average the in-image samples only
+    let coverage = blur::box_blur_values(&vec![[1.0; 4]; width * height], width, height, radius, edge);
+    let window_means = |values: &[[f32; 4]]| -> Vec<[f32; 4]> {
+        blur::box_blur_values(values, width, height, radius, edge).iter().zip(coverage.iter()).map(|(sum, share)| {
+            [sum[0] / share[0], sum[1] / share[0], sum[2] / share[0], sum[3] / share[0]]
+        }).collect()
+    };
+
+    // Window means of the guide, the input, the guide squared and guide times input, in one pass
+    let products: Vec<[f32; 4]> = guide.iter().zip(plane.iter()).map(|(g, p)| [*g, *p, g * g, g * p]).collect();
+    let means = window_means(&products);
+
+    // Slope and offset of the linear fit in every window
+    let coefficients: Vec<[f32; 4]> = means.iter().map(|[guide, input, guide_squared, product]| {
+        let variance = guide_squared - guide * guide;
+        let covariance = product - guide * input;
+        let slope = covariance / (variance + epsilon);
+        [slope, input - slope * guide, 0.0, 0.0]
+    }).collect();
+
+    // Every pixel lies in many windows, so average their fits
+    let coefficients = window_means(&coefficients);
+    coefficients.iter().zip(guide.iter()).map(|([slope, offset, _, _], guide)| slope * guide + offset).collect()
+}
+
+// Guided filter: smooths the image while keeping the edges found in the guide's luminance
+// Using the image as its own guide gives edge-preserving smoothing; epsilon is the squared
+// contrast (as a fraction of full intensity) below which detail is smoothed away
+pub fn guided_filter_image<P: Pixel, G: Pixel>(image: &Image<P>, guide: &Image<G>, radius: usize, epsilon: f32, mode: ChannelMode, edge: Edge) -> Image<P> {
+    if !image.same_dimensions(guide) {
+        panic!("Images have different dimensions");
+    }
+    let (width, height) = (image.width(), image.height());
+    let guide: Vec<f32> = guide.data.iter().map(|pixel| {
+        let bgra = pixel.to_bgra();
+        pixel::luma(bgra[0], bgra[1], bgra[2])
+    }).collect();
+    filter_planes(image, mode, |plane| guided_plane(plane, &guide, width, height, radius, epsilon, edge))
+}
+
+// Bilateral filter of an image
+pub fn bilateral_filter<P: Pixel>(image: &Data<P>, spatial_sigma: f32, range_sigma: f32, mode: ChannelMode, edge: Edge) -> Data<P> {
+    image.with_pixels(bilateral_filter_image(&image.pixels, spatial_sigma, range_sigma, mode, edge))
+}
+
+// Guided filter of an image, with the guide lined up as displayed even if its rows are stored the other way
+pub fn guided_filter<P: Pixel, G: Pixel>(image: &Data<P>, guide: &Data<G>, radius: usize, epsilon: f32, mode: ChannelMode, edge: Edge) -> Data<P> {
+    let pixels = if image.header.is_top_down() == guide.header.is_top_down() {
+        guided_filter_image(&image.pixels, &guide.pixels, radius, epsilon, mode, edge)
+    }
+    else {
+        guided_filter_image(&image.pixels, &guide.pixels.flip_vertical(), radius, epsilon, mode, edge)
+    };
+    image.with_pixels(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::{Bgr, Gray};
+
+    // Dark left half and light right half, with a little noise on both
+    fn noisy_step() -> Image<Gray<u8>> {
+        Image::from_fn(16, 8, |x, y| {
+            let noise = ((x * 7 + y * 13) % 5) as u8 * 2;
+            Gray {value: if x < 8 { 60 + noise } else { 190 + noise }}
+        })
+    }
+
+    // Largest difference between neighbouring pixels within the left half
+    fn noise_left(image: &Image<Gray<u8>>) -> u8 {
+        image.rows().flat_map(|row| row[..8].windows(2).map(|pair| pair[0].value.abs_diff(pair[1].value))).max().unwrap()
+    }
+
+    #[test]
+    fn test_bilateral_keeps_edges() {
+        let image = noisy_step();
+        let smoothed = bilateral_filter_image(&image, 2.0, 0.1, ChannelMode::PerChannel, Edge::Clamp);
+        assert!(noise_left(&smoothed) < noise_left(&image));
+
+        // The two sides stay as far apart as they were
+        assert!(smoothed.get(7, 4).value < 75);
+        assert!(smoothed.get(8, 4).value > 185);
+    }
+
+    #[test]
+    fn test_guided_filter_keeps_edges() {
+        let image = noisy_step();
+        let smoothed = guided_filter_image(&image, &image, 2, 0.001, ChannelMode::PerChannel, Edge::Clamp);
+        assert!(noise_left(&smoothed) < noise_left(&image));
+        assert!(smoothed.get(7, 4).value < 75);
+        assert!(smoothed.get(8, 4).value > 185);
+
+        // With a flat guide there are no edges to keep and it becomes a box blur
+        let flat = Image::new(16, 8, Gray {value: 128u8});
+        let blurred = guided_filter_image(&image, &flat, 2, 0.001, ChannelMode::PerChannel, Edge::Clamp);
+        assert!(blurred.get(7, 4).value > 90 && blurred.get(8, 4).value < 160);
+    }
+
+    #[test]
+    fn test_flat_image_stays_flat_with_every_edge() {
+        let image = Image::new(9, 9, Gray {value: 128u8});
+        for edge in [Edge::Clamp, Edge::Wrap, Edge::Mirror, Edge::Constant] {
+            assert_eq!(guided_filter_image(&image, &image, 2, 0.01, ChannelMode::PerChannel, edge), image, "{:?}", edge);
+            assert_eq!(bilateral_filter_image(&image, 1.0, 0.1, ChannelMode::PerChannel, edge), image, "{:?}", edge);
+        }
+    }
+
+    #[test]
+    fn test_luminance_mode_keeps_color_differences() {
+        // A red pixel and a green pixel of the same brightness are left apart in luminance mode
+        let image = Image::from_fn(4, 1, |x, _| {
+            if x % 2 == 0 { Bgr {blue: 0u8, green: 0, red: 200} } else { Bgr {blue: 0, green: 102, red: 0} }
+        });
+        let smoothed = bilateral_filter_image(&image, 1.0, 0.5, ChannelMode::Luminance, Edge::Clamp);
+        assert!(smoothed.get(0, 0).red >= 195 && smoothed.get(0, 0).green <= 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Images have different dimensions")]
+    fn test_guide_must_match() {
+        let image = Image::new(4, 4, Gray {value: 0u8});
+        let guide = Image::new(4, 3, Gray {value: 0u8});
+        guided_filter_image(&image, &guide, 1, 0.01, ChannelMode::PerChannel, Edge::Clamp);
+    }
+}
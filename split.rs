@@ -0,0 +1,284 @@
+// Splitting images into single-channel images and merging them back, with channels chosen by name
+// Besides the stored red, green, blue and alpha, channels can be luma or components of the HSV,
+// CIELAB and LCh color models. Every channel is stored normalized to 0 to 1 in the single-channel
+// images: hues are divided by 360, lightness by 100, Lab a and b are shifted by 128 and divided
+// by 255 (the usual 8-bit Lab encoding), and LCh chroma is divided by 150, which covers sRGB
+
+use std::io;
+
+use crate::color;
+use crate::image::Image;
+use crate::pixel::{self, Pixel};
+use crate::Data;
+
+// A channel of an image, stored or computed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Component {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    // Rec. 601 luma, the brightness used for grayscale
+    Luma,
+    Hue,
+    Saturation,
+    Value,
+    LabLightness,
+    LabA,
+    LabB,
+    LchLightness,
+    LchChroma,
+    LchHue,
+}
+
+// Color models that merged channels can be written in
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Model {
+    Rgb,
+    Hsv,
+    Lab,
+    Lch,
+}
+
+impl Component {
+    // Looks a channel up by name, ignoring case: red (r), green (g), blue (b), alpha (a), luma (y),
+    // hue (h), saturation (s), value (v), lab-l (l*), lab-a (a*), lab-b (b*), lch-l, lch-c (c*) and lch-h
+    pub fn parse(name: &str) -> Result<Component, io::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "red" | "r" => Ok(Component::Red),
+            "green" | "g" => Ok(Component::Green),
+            "blue" | "b" => Ok(Component::Blue),
+            "alpha" | "a" => Ok(Component::Alpha),
+            "luma" | "y" => Ok(Component::Luma),
+            "hue" | "h" => Ok(Component::Hue),
+            "saturation" | "s" => Ok(Component::Saturation),
+            "value" | "v" => Ok(Component::Value),
+            "lab-l" | "l*" => Ok(Component::LabLightness),
+            "lab-a" | "a*" => Ok(Component::LabA),
+            "lab-b" | "b*" => Ok(Component::LabB),
+            "lch-l" => Ok(Component::LchLightness),
+            "lch-c" | "c*" => Ok(Component::LchChroma),
+            "lch-h" => Ok(Component::LchHue),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown channel: {}", name))),
+        }
+    }
+
+    // Short name of the channel, one that parse accepts
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Red => "red",
+            Component::Green => "green",
+            Component::Blue => "blue",
+            Component::Alpha => "alpha",
+            Component::Luma => "luma",
+            Component::Hue => "hue",
+            Component::Saturation => "saturation",
+            Component::Value => "value",
+            Component::LabLightness => "lab-l",
+            Component::LabA => "lab-a",
+            Component::LabB => "lab-b",
+            Component::LchLightness => "lch-l",
+            Component::LchChroma => "lch-c",
+            Component::LchHue => "lch-h",
+        }
+    }
+
+    // Color model the channel belongs to and its position there, None for alpha and luma
+    fn slot(self) -> Option<(Model, usize)> {
+        match self {
+            Component::Red => Some((Model::Rgb, 0)),
+            Component::Green => Some((Model::Rgb, 1)),
+            Component::Blue => Some((Model::Rgb, 2)),
+            Component::Hue => Some((Model::Hsv, 0)),
+            Component::Saturation => Some((Model::Hsv, 1)),
+            Component::Value => Some((Model::Hsv, 2)),
+            Component::LabLightness => Some((Model::Lab, 0)),
+            Component::LabA => Some((Model::Lab, 1)),
+            Component::LabB => Some((Model::Lab, 2)),
+            Component::LchLightness => Some((Model::Lch, 0)),
+            Component::LchChroma => Some((Model::Lch, 1)),
+            Component::LchHue => Some((Model::Lch, 2)),
+            Component::Alpha | Component::Luma => None,
+        }
+    }
+
+    // Normalized value of this channel for a pixel's normalized BGRA values
+    pub fn read(self, bgra: [f32; 4]) -> f32 {
+        let rgb = [bgra[2], bgra[1], bgra[0]];
+        match self.slot() {
+            Some((model, index)) => to_normalized(model, rgb)[index],
+            None if self == Component::Alpha => bgra[3],
+            None => pixel::luma(bgra[0], bgra[1], bgra[2]),
+        }
+    }
+}
+
+// A color's components in a model, normalized to 0 to 1
+fn to_normalized(model: Model, rgb: [f32; 3]) -> [f32; 3] {
+    match model {
+        Model::Rgb => rgb,
+        Model::Hsv => {
+            let [hue, saturation, value] = color::rgb_to_hsv(rgb);
+            [hue / 360.0, saturation, value]
+        }
+        Model::Lab => {
+            let [lightness, a, b] = color::rgb_to_lab(rgb);
+            [lightness / 100.0, (a + 128.0) / 255.0, (b + 128.0) / 255.0]
+        }
+        Model::Lch => {
+            let [lightness, chroma, hue] = color::rgb_to_lch(rgb);
+            [lightness / 100.0, chroma / 150.0, hue / 360.0]
+        }
+    }
+}
+
+// The color given by normalized components in a model
+fn from_normalized(model: Model, components: [f32; 3]) -> [f32; 3] {
+    match model {
+        Model::Rgb => components,
+        Model::Hsv => color::hsv_to_rgb([components[0] * 360.0, components[1], components[2]]),
+        Model::Lab => color::lab_to_rgb([components[0] * 100.0, components[1] * 255.0 - 128.0, components[2] * 255.0 - 128.0]),
+        Model::Lch => color::lch_to_rgb([components[0] * 100.0, components[1] * 150.0, components[2] * 360.0]),
+    }
+}
+
+// Normalized components of black in a model, used for channels a merge doesn't provide
+fn black(model: Model) -> [f32; 3] {
+    match model {
+        Model::Rgb | Model::Hsv | Model::Lch => [0.0; 3],
+        Model::Lab => [0.0, 128.0 / 255.0, 128.0 / 255.0],
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+// One channel of an image as a grayscale image of any pixel layout
+pub fn split_channel_image<P: Pixel, Q: Pixel>(image: &Image<P>, component: Component) -> Image<Q> {
+    image.map(|pixel| {
+        let value = component.read(pixel.to_bgra());
+        Q::from_bgra([value, value, value, 1.0])
+    })
+}
+
+// Several channels of an image, each as its own grayscale image
+pub fn split_channels<P: Pixel, Q: Pixel>(image: &Data<P>, components: &[Component]) -> Vec<Data<Q>> {
+    components.iter().map(|component| image.with_pixels(split_channel_image(&image.pixels, *component))).collect()
+}
+
+// Builds an image from single-channel inputs, each given as (image, channel read from it, channel written)
+// The written channels must all come from one color model (RGB, HSV, Lab or LCh), plus optionally alpha;
+// channels nobody writes are black, and alpha is opaque unless written
+pub fn merge_channels_image<P: Pixel, Q: Pixel>(inputs: &[(&Image<P>, Component, Component)]) -> Result<Image<Q>, io::Error> {
+    let Some((first, _, _)) = inputs.first() else {
+        return Err(invalid_input("No channels to merge"));
+    };
+    if inputs.iter().any(|(image, _, _)| !image.same_dimensions(first)) {
+        return Err(invalid_input("Images have different dimensions"));
+    }
+
+    // Every written channel must be distinct and the color ones must share a model
+    let mut model = None;
+    for (index, (_, _, target)) in inputs.iter().enumerate() {
+        if inputs[..index].iter().any(|(_, _, other)| other == target) {
+            return Err(invalid_input(&format!("Channel {:?} is written more than once", target)));
+        }
+        match (target.slot(), model) {
+            (None, _) if *target == Component::Luma => return Err(invalid_input("Luma can't be written, merge red, green and blue instead")),
+            (Some((target_model, _)), Some(model)) if target_model != model => {
+                return Err(invalid_input("Merged channels must come from one color model"));
+            }
+            (Some((target_model, _)), _) => model = Some(target_model),
+            _ => {}
+        }
+    }
+    let model = model.unwrap_or(Model::Rgb);
+
+    let data = (0..first.data.len()).map(|i| {
+        let mut components = black(model);
+        let mut alpha = 1.0;
+        for (image, source, target) in inputs {
+            let value = source.read(image.data[i].to_bgra());
+            match target.slot() {
+                Some((_, index)) => components[index] = value,
+                None => alpha = value,
+            }
+        }
+        let [red, green, blue] = from_normalized(model, components);
+        Q::from_bgra([blue, green, red, alpha])
+    }).collect();
+    Ok(Image::from_vec(first.width(), first.height(), data))
+}
+
+// Builds an image from single-channel inputs given as (image, channel read, channel written),
+// lining every input up with the first one as displayed; the header is taken from the first input
+pub fn merge_channels<P: Pixel, Q: Pixel>(inputs: &[(&Data<P>, Component, Component)]) -> Result<Data<Q>, io::Error> {
+    let Some((first, _, _)) = inputs.first() else {
+        return Err(invalid_input("No channels to merge"));
+    };
+
+    // Inputs stored the other way up are flipped to match the first
+    let upright: Vec<Image<P>> = inputs.iter().map(|(image, _, _)| {
+        if image.header.is_top_down() == first.header.is_top_down() { image.pixels.clone() } else { image.pixels.flip_vertical() }
+    }).collect();
+    let assignments: Vec<(&Image<P>, Component, Component)> = upright.iter().zip(inputs.iter()).map(|(pixels, (_, source, target))| (pixels, *source, *target)).collect();
+    Ok(first.with_pixels(merge_channels_image(&assignments)?))
+}
+
+// Same as merge_channels, with channels given by name
+pub fn merge_channels_by_name<P: Pixel, Q: Pixel>(inputs: &[(&Data<P>, &str, &str)]) -> Result<Data<Q>, io::Error> {
+    let inputs = inputs.iter().map(|(image, source, target)| Ok((*image, Component::parse(source)?, Component::parse(target)?))).collect::<Result<Vec<_>, io::Error>>()?;
+    merge_channels(&inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::{Bgr, Bgra, Gray};
+
+    #[test]
+    fn test_split_and_merge_round_trip() {
+        let image = Image::from_fn(4, 3, |x, y| Bgra {blue: (x * 60) as u8, green: (y * 100) as u8, red: 200, alpha: (x * 80) as u8});
+
+        // Through RGB with alpha
+        let channels = [Component::Red, Component::Green, Component::Blue, Component::Alpha];
+        let split: Vec<Image<Gray<u8>>> = channels.iter().map(|channel| split_channel_image(&image, *channel)).collect();
+        let inputs: Vec<(&Image<Gray<u8>>, Component, Component)> = split.iter().zip(channels).map(|(plane, channel)| (plane, Component::Luma, channel)).collect();
+        assert_eq!(merge_channels_image::<_, Bgra<u8>>(&inputs).unwrap(), image);
+
+        // Through HSV, kept as floats so nothing is lost to rounding
+        let channels = [Component::Hue, Component::Saturation, Component::Value, Component::Alpha];
+        let split: Vec<Image<Gray<f32>>> = channels.iter().map(|channel| split_channel_image(&image, *channel)).collect();
+        let inputs: Vec<(&Image<Gray<f32>>, Component, Component)> = split.iter().zip(channels).map(|(plane, channel)| (plane, Component::Luma, channel)).collect();
+        assert_eq!(merge_channels_image::<_, Bgra<u8>>(&inputs).unwrap(), image);
+
+        // Through LCh
+        let channels = [Component::LchLightness, Component::LchChroma, Component::LchHue, Component::Alpha];
+        let split: Vec<Image<Gray<f32>>> = channels.iter().map(|channel| split_channel_image(&image, *channel)).collect();
+        let inputs: Vec<(&Image<Gray<f32>>, Component, Component)> = split.iter().zip(channels).map(|(plane, channel)| (plane, Component::Luma, channel)).collect();
+        assert_eq!(merge_channels_image::<_, Bgra<u8>>(&inputs).unwrap(), image);
+    }
+
+    #[test]
+    fn test_lab_channels() {
+        // Gray has no Lab color, so a and b sit at the middle of their range
+        let gray = Bgra {blue: 0.5f32, green: 0.5, red: 0.5, alpha: 1.0}.to_bgra();
+        assert!((Component::LabA.read(gray) - 128.0 / 255.0).abs() < 1e-4);
+        assert!((Component::LabLightness.read([1.0; 4]) - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_merge_validation() {
+        let small = Image::new(2, 2, Bgr {blue: 0u8, green: 0, red: 0});
+        let large = Image::new(3, 2, Bgr {blue: 0u8, green: 0, red: 0});
+        let merge = |inputs: &[(&Image<Bgr>, Component, Component)]| merge_channels_image::<_, Bgr>(inputs).unwrap_err().to_string();
+
+        assert_eq!(merge(&[(&small, Component::Red, Component::Red), (&large, Component::Green, Component::Green)]), "Images have different dimensions");
+        assert_eq!(merge(&[(&small, Component::Red, Component::Red), (&small, Component::Hue, Component::Hue)]), "Merged channels must come from one color model");
+        assert_eq!(merge(&[(&small, Component::Red, Component::Red), (&small, Component::Green, Component::Red)]), "Channel Red is written more than once");
+        assert_eq!(merge(&[]), "No channels to merge");
+        assert!(Component::parse("purple").is_err());
+        assert_eq!(Component::parse("L*").unwrap(), Component::LabLightness);
+    }
+}
@@ -0,0 +1,205 @@
+// Channel swizzles: reordering, duplicating, zeroing or filling channels, and raw export in any channel order
+// Specs are written two ways:
+//   a short form listing where the output red, green, blue (and alpha) come from, e.g. "rrr1" or "g0b1",
+//   using r, g, b, a for channels and 0 or 1 for constants; with three letters alpha is kept
+//   a layout change "from->to", e.g. "bgra->rgba": data stored in the order of the left side is
+//   rewritten in the order of the right side, so a TGA file (stored BGRA) ends up with RGBA bytes
+
+use std::fs;
+use std::io;
+
+use crate::channel::Channel;
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::Data;
+
+// Where a channel's value comes from
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Source {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Zero,
+    One,
+}
+
+impl Source {
+    // Source named by a single letter: r, g, b, a, 0 or 1 (letters may be upper case)
+    pub fn parse(letter: char) -> Result<Source, io::Error> {
+        match letter.to_ascii_lowercase() {
+            'r' => Ok(Source::Red),
+            'g' => Ok(Source::Green),
+            'b' => Ok(Source::Blue),
+            'a' => Ok(Source::Alpha),
+            '0' => Ok(Source::Zero),
+            '1' => Ok(Source::One),
+            _ => Err(invalid_input(&format!("Unknown swizzle channel: {}", letter))),
+        }
+    }
+
+    // Value of this source for a pixel's normalized BGRA values
+    pub fn read(self, bgra: [f32; 4]) -> f32 {
+        match self {
+            Source::Red => bgra[2],
+            Source::Green => bgra[1],
+            Source::Blue => bgra[0],
+            Source::Alpha => bgra[3],
+            Source::Zero => 0.0,
+            Source::One => 1.0,
+        }
+    }
+
+    // Position of a channel in RGBA order, None for constants
+    fn rgba_index(self) -> Option<usize> {
+        match self {
+            Source::Red => Some(0),
+            Source::Green => Some(1),
+            Source::Blue => Some(2),
+            Source::Alpha => Some(3),
+            Source::Zero | Source::One => None,
+        }
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+// Sources of a run of letters, e.g. "rgb1"
+pub fn parse_order(text: &str) -> Result<Vec<Source>, io::Error> {
+    let order = text.trim().chars().map(Source::parse).collect::<Result<Vec<Source>, io::Error>>()?;
+    if order.is_empty() {
+        return Err(invalid_input("Channel order is empty"));
+    }
+    Ok(order)
+}
+
+// Sources of the output red, green, blue and alpha
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Swizzle {
+    pub sources: [Source; 4],
+}
+
+impl Swizzle {
+    // Leaves every channel as it is
+    pub fn identity() -> Swizzle {
+        Swizzle {sources: [Source::Red, Source::Green, Source::Blue, Source::Alpha]}
+    }
+
+    // Swizzle from a short form ("rrr1", "bgr") or a layout change ("bgra->rgba")
+    pub fn parse(spec: &str) -> Result<Swizzle, io::Error> {
+        match spec.split_once("->") {
+            Some((from, to)) => Swizzle::from_layouts(&parse_order(from)?, &parse_order(to)?),
+            None => {
+                let order = parse_order(spec)?;
+                let mut swizzle = Swizzle::identity();
+                match order.len() {
+                    3 | 4 => swizzle.sources[..order.len()].copy_from_slice(&order),
+                    count => return Err(invalid_input(&format!("Swizzle needs 3 or 4 channels, got {}", count))),
+                }
+                Ok(swizzle)
+            }
+        }
+    }
+
+    // Swizzle that rewrites data stored in the order `from` in the order `to`
+    // Whatever channel `from` names at a position receives the value `to` names there
+    pub fn from_layouts(from: &[Source], to: &[Source]) -> Result<Swizzle, io::Error> {
+        if from.len() != to.len() {
+            return Err(invalid_input("Both sides of a swizzle need the same number of channels"));
+        }
+        let mut swizzle = Swizzle::identity();
+        for (index, (target, source)) in from.iter().zip(to.iter()).enumerate() {
+            let Some(slot) = target.rgba_index() else {
+                return Err(invalid_input("The left side of a swizzle can't hold constants"));
+            };
+            if from[..index].contains(target) {
+                return Err(invalid_input(&format!("Channel {:?} appears more than once on the left side of a swizzle", target)));
+            }
+            swizzle.sources[slot] = *source;
+        }
+        Ok(swizzle)
+    }
+
+    // Swizzles one pixel's normalized BGRA values
+    pub fn apply(&self, bgra: [f32; 4]) -> [f32; 4] {
+        let [red, green, blue, alpha] = self.sources.map(|source| source.read(bgra));
+        [blue, green, red, alpha]
+    }
+}
+
+// Swizzles the channels of every pixel
+pub fn swizzle_image<P: Pixel>(image: &Image<P>, swizzle: &Swizzle) -> Image<P> {
+    image.map(|pixel| P::from_bgra(swizzle.apply(pixel.to_bgra())))
+}
+
+// Swizzles the channels of an image
+pub fn swizzle<P: Pixel>(image: &Data<P>, swizzle: &Swizzle) -> Data<P> {
+    image.with_pixels(swizzle_image(&image.pixels, swizzle))
+}
+
+// 8-bit channel values of every pixel in the given order, rows in the order they are stored
+pub fn raw_bytes<P: Pixel>(image: &Image<P>, order: &[Source]) -> Vec<u8> {
+    image.data.iter().flat_map(|pixel| {
+        let bgra = pixel.to_bgra();
+        order.iter().map(move |source| u8::from_f32(source.read(bgra)))
+    }).collect()
+}
+
+// Writes the pixels with no header, channels in the given order and the top row first,
+// the layout most engines and GPU uploads expect
+pub fn export_raw<P: Pixel>(file_path: &str, image: &Data<P>, order: &[Source]) -> Result<(), io::Error> {
+    let bytes = if image.header.is_top_down() {
+        raw_bytes(&image.pixels, order)
+    }
+    else {
+        raw_bytes(&image.pixels.flip_vertical(), order)
+    };
+    fs::write(file_path, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::{Bgr, Bgra};
+
+    #[test]
+    fn test_parse_forms() {
+        assert_eq!(Swizzle::parse("rgba").unwrap(), Swizzle::identity());
+        assert_eq!(Swizzle::parse("rrr1").unwrap().sources, [Source::Red, Source::Red, Source::Red, Source::One]);
+        assert_eq!(Swizzle::parse("G0B").unwrap().sources, [Source::Green, Source::Zero, Source::Blue, Source::Alpha]);
+
+        // Swapping the outer channels of a layout swaps red and blue
+        let swap = [Source::Blue, Source::Green, Source::Red, Source::Alpha];
+        assert_eq!(Swizzle::parse("bgra->rgba").unwrap().sources, swap);
+        assert_eq!(Swizzle::parse("rgb->bgr").unwrap().sources, swap);
+        assert_eq!(Swizzle::parse("a->1").unwrap().sources, [Source::Red, Source::Green, Source::Blue, Source::One]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Swizzle::parse("rg").is_err());
+        assert!(Swizzle::parse("rgbx").is_err());
+        assert!(Swizzle::parse("rgb->rg").is_err());
+        assert!(Swizzle::parse("rg1->rgb").is_err());
+        assert!(Swizzle::parse("rrb->rgb").is_err());
+        assert!(parse_order("").is_err());
+    }
+
+    #[test]
+    fn test_swizzle_and_raw_bytes() {
+        let image = Image::new(1, 1, Bgra {blue: 10u8, green: 20, red: 30, alpha: 40});
+        let swizzled = swizzle_image(&image, &Swizzle::parse("g0b1").unwrap());
+        assert_eq!(swizzled.get(0, 0), Bgra {blue: 10, green: 0, red: 20, alpha: 255});
+
+        // After a BGRA to RGBA swizzle the bytes of a TGA file read red first
+        let converted = swizzle_image(&image, &Swizzle::parse("bgra->rgba").unwrap());
+        assert_eq!(raw_bytes(&converted, &parse_order("bgra").unwrap()), vec![30, 20, 10, 40]);
+        assert_eq!(raw_bytes(&image, &parse_order("rgba").unwrap()), vec![30, 20, 10, 40]);
+
+        // Layouts without alpha read as opaque
+        let opaque = Image::new(2, 1, Bgr {blue: 1u8, green: 2, red: 3});
+        assert_eq!(raw_bytes(&opaque, &parse_order("ra0").unwrap()), vec![3, 255, 0, 3, 255, 0]);
+    }
+}
@@ -0,0 +1,189 @@
+// Borrowed rectangular regions of an image
+// Views keep the stride of the image they came from, so operations on a region work in place
+
+use crate::image::Image;
+use crate::pixel::Pixel;
+
+// Read-only region of an image
+#[derive(Clone, Copy)]
+pub struct View<'a, P: Pixel> {
+    data: &'a [P],
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+// Mutable region of an image
+pub struct ViewMut<'a, P: Pixel> {
+    data: &'a mut [P],
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+// Range of the parent's pixel data covered by a region, from its first pixel to its last
+fn region_range(x: usize, y: usize, width: usize, height: usize, stride: usize) -> std::ops::Range<usize> {
+    if width == 0 || height == 0 {
+        return 0..0;
+    }
+    let start = y * stride + x;
+    start..start + (height - 1) * stride + width
+}
+
+// Makes sure a region fits inside its parent
+fn check_region(x: usize, y: usize, width: usize, height: usize, parent_width: usize, parent_height: usize) {
+    if x + width > parent_width || y + height > parent_height {
+        panic!("Region is outside the image");
+    }
+}
+
+impl<P: Pixel> Image<P> {
+    // Read-only view of the rectangle starting at (x, y)
+    pub fn view(&self, x: usize, y: usize, width: usize, height: usize) -> View<'_, P> {
+        check_region(x, y, width, height, self.width(), self.height());
+        let stride = self.width();
+        View {data: &self.data[region_range(x, y, width, height, stride)], width, height, stride}
+    }
+
+    // Mutable view of the rectangle starting at (x, y)
+    pub fn view_mut(&mut self, x: usize, y: usize, width: usize, height: usize) -> ViewMut<'_, P> {
+        check_region(x, y, width, height, self.width(), self.height());
+        let stride = self.width();
+        ViewMut {data: &mut self.data[region_range(x, y, width, height, stride)], width, height, stride}
+    }
+
+    // View of the whole image
+    pub fn as_view(&self) -> View<'_, P> {
+        self.view(0, 0, self.width(), self.height())
+    }
+
+    // Mutable view of the whole image
+    pub fn as_view_mut(&mut self) -> ViewMut<'_, P> {
+        let (width, height) = (self.width(), self.height());
+        self.view_mut(0, 0, width, height)
+    }
+}
+
+impl<'a, P: Pixel> View<'a, P> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Pixels of one row of the region
+    pub fn row(&self, y: usize) -> &'a [P] {
+        &self.data[y * self.stride..y * self.stride + self.width]
+    }
+
+    // Builds a new image by applying a function to every pixel of the region
+    pub fn map<Q: Pixel>(&self, mut f: impl FnMut(P) -> Q) -> Image<Q> {
+        Image::from_fn(self.width, self.height, |x, y| f(self.data[y * self.stride + x]))
+    }
+
+    // Copies the region into a new image
+    pub fn to_image(self) -> Image<P> {
+        self.map(|pixel| pixel)
+    }
+}
+
+impl<'a, P: Pixel> ViewMut<'a, P> {
+    // Iterates over the region's mutable rows from top to bottom
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [P]> {
+        let width = self.width;
+        self.data.chunks_mut(self.stride.max(1)).take(self.height).map(move |row| &mut row[..width])
+    }
+
+    // Modifies every pixel in place using the matching pixel of another region with the same size
+    pub fn zip_map_in_place<Q: Pixel>(&mut self, other: &View<Q>, mut f: impl FnMut(&mut P, Q)) {
+        if self.width != other.width() || self.height != other.height() {
+            panic!("Regions have different dimensions");
+        }
+        for (y, row) in self.rows_mut().enumerate() {
+            for (pixel, other_pixel) in row.iter_mut().zip(other.row(y)) {
+                f(pixel, *other_pixel);
+            }
+        }
+    }
+
+    // Copies another region with the same size into this one
+    pub fn copy_from(&mut self, other: &View<P>) {
+        self.zip_map_in_place(other, |pixel, other_pixel| *pixel = other_pixel);
+    }
+
+    // Flips the region upside down in place
+    pub fn flip_vertical(&mut self) {
+        let (width, stride, height) = (self.width, self.stride, self.height);
+        for y in 0..height / 2 {
+            // Split so the top and bottom rows can be borrowed at the same time
+            let (top, bottom) = self.data.split_at_mut((height - 1 - y) * stride);
+            top[y * stride..y * stride + width].swap_with_slice(&mut bottom[..width]);
+        }
+    }
+
+    // Mirrors the region left to right in place
+    pub fn flip_horizontal(&mut self) {
+        for row in self.rows_mut() {
+            row.reverse();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    // 4x3 image where each value encodes its position
+    fn numbered_image() -> Image<Gray<u8>> {
+        Image::from_fn(4, 3, |x, y| Gray {value: (y * 10 + x) as u8})
+    }
+
+    // Values of an image in row-major order
+    fn values(image: &Image<Gray<u8>>) -> Vec<u8> {
+        image.data.iter().map(|pixel| pixel.value).collect()
+    }
+
+    #[test]
+    fn test_view_reads_region() {
+        let image = numbered_image();
+        let view = image.view(1, 1, 2, 2);
+        assert_eq!(view.row(0)[1].value, 12);
+        assert_eq!(values(&view.to_image()), vec![11, 12, 21, 22]);
+    }
+
+    #[test]
+    fn test_view_mut_changes_only_region() {
+        let mut image = numbered_image();
+        let hundreds = Image::new(2, 3, Gray {value: 100u8});
+        image.view_mut(2, 0, 2, 3).zip_map_in_place(&hundreds.as_view(), |pixel, other| pixel.value += other.value);
+        assert_eq!(values(&image), vec![0, 1, 102, 103, 10, 11, 112, 113, 20, 21, 122, 123]);
+    }
+
+    #[test]
+    fn test_region_flips() {
+        let mut image = numbered_image();
+        image.view_mut(1, 0, 3, 3).flip_vertical();
+        assert_eq!(values(&image), vec![0, 21, 22, 23, 10, 11, 12, 13, 20, 1, 2, 3]);
+
+        let mut image = numbered_image();
+        image.view_mut(0, 1, 3, 2).flip_horizontal();
+        assert_eq!(values(&image), vec![0, 1, 2, 3, 12, 11, 10, 13, 22, 21, 20, 23]);
+    }
+
+    #[test]
+    fn test_copy_between_regions() {
+        let source = numbered_image();
+        let mut target = Image::new(4, 3, Gray {value: 0u8});
+        target.view_mut(2, 1, 2, 2).copy_from(&source.view(0, 0, 2, 2));
+        assert_eq!(values(&target), vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 10, 11]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Region is outside the image")]
+    fn test_view_checks_bounds() {
+        numbered_image().view(3, 0, 2, 1);
+    }
+}
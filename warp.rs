@@ -0,0 +1,290 @@
+// Affine and perspective warps using 3x3 matrices in homogeneous coordinates
+// Every destination pixel is mapped back into the source (inverse mapping) and
+// resampled there, so the result has no holes whatever the transform
+
+use std::io;
+
+use crate::image::Image;
+use crate::pixel::Pixel;
+use crate::resample::{self, Edge, Interpolation};
+use crate::Data;
+
+// Maps (x, y) to ((a x + b y + c) / w, (d x + e y + f) / w) where w = g x + h y + i
+// Affine transforms keep the last row at [0, 0, 1]; anything else is a perspective transform
+// Positions are in pixels with pixel centers on whole numbers and y pointing down
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    pub matrix: [[f64; 3]; 3],
+}
+
+impl Transform {
+    // Leaves every point where it is
+    pub fn identity() -> Transform {
+        Transform {matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]}
+    }
+
+    // Moves every point by (dx, dy)
+    pub fn translate(dx: f64, dy: f64) -> Transform {
+        Transform {matrix: [[1.0, 0.0, dx], [0.0, 1.0, dy], [0.0, 0.0, 1.0]]}
+    }
+
+    // Stretches away from the origin by sx horizontally and sy vertically
+    pub fn scale(sx: f64, sy: f64) -> Transform {
+        Transform {matrix: [[sx, 0.0, 0.0], [0.0, sy, 0.0], [0.0, 0.0, 1.0]]}
+    }
+
+    // Turns clockwise about the origin as displayed (y points down)
+    pub fn rotate(degrees: f64) -> Transform {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Transform {matrix: [[cos, -sin, 0.0], [sin, cos, 0.0], [0.0, 0.0, 1.0]]}
+    }
+
+    // Slants the image: x moves by shear_x * y and y moves by shear_y * x
+    pub fn shear(shear_x: f64, shear_y: f64) -> Transform {
+        Transform {matrix: [[1.0, shear_x, 0.0], [shear_y, 1.0, 0.0], [0.0, 0.0, 1.0]]}
+    }
+
+    // One step such as translate=<dx>,<dy>, scale=<factor>[,<factor y>], rotate=<degrees>,
+    // shear=<x>,<y> or matrix=<a>,...,<i> (row by row, or the top two rows of an affine matrix)
+    pub fn parse(text: &str) -> Result<Transform, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid transform: {}", text));
+        let (name, values) = text.split_once('=').ok_or_else(invalid)?;
+        let values = values.split(',').map(|value| value.trim().parse::<f64>().map_err(|_| invalid())).collect::<Result<Vec<f64>, io::Error>>()?;
+
+        match (name.trim().to_ascii_lowercase().as_str(), values.as_slice()) {
+            ("translate", &[dx, dy]) => Ok(Transform::translate(dx, dy)),
+            ("scale", &[factor]) => Ok(Transform::scale(factor, factor)),
+            ("scale", &[sx, sy]) => Ok(Transform::scale(sx, sy)),
+            ("rotate", &[degrees]) => Ok(Transform::rotate(degrees)),
+            ("shear", &[shear_x, shear_y]) => Ok(Transform::shear(shear_x, shear_y)),
+            ("matrix", &[a, b, c, d, e, f]) => Ok(Transform {matrix: [[a, b, c], [d, e, f], [0.0, 0.0, 1.0]]}),
+            ("matrix", &[a, b, c, d, e, f, g, h, i]) => Ok(Transform {matrix: [[a, b, c], [d, e, f], [g, h, i]]}),
+            _ => Err(invalid()),
+        }
+    }
+
+    // Applies this transform first and then the other one
+    pub fn then(&self, other: &Transform) -> Transform {
+        let (a, b) = (&other.matrix, &self.matrix);
+        let mut matrix = [[0.0; 3]; 3];
+        for (row, values) in matrix.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = (0..3).map(|k| a[row][k] * b[k][column]).sum();
+            }
+        }
+        Transform {matrix}
+    }
+
+    // Transform that undoes this one, None when it flattens the plane (zero determinant)
+    pub fn inverse(&self) -> Option<Transform> {
+        let m = &self.matrix;
+        let cofactor = |row: usize, column: usize| {
+            let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+            let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let determinant: f64 = (0..3).map(|column| m[0][column] * cofactor(0, column)).sum();
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+
+        // The inverse is the transposed cofactor matrix over the determinant
+        let mut matrix = [[0.0; 3]; 3];
+        for (row, values) in matrix.iter_mut().enumerate() {
+            for (column, value) in values.iter_mut().enumerate() {
+                *value = cofactor(column, row) / determinant;
+            }
+        }
+        Some(Transform {matrix})
+    }
+
+    // Where a point ends up, None when it is sent to infinity or behind the viewer
+    pub fn apply(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let m = &self.matrix;
+        let w = m[2][0] * x + m[2][1] * y + m[2][2];
+        if w <= 1e-12 {
+            return None;
+        }
+        Some(((m[0][0] * x + m[0][1] * y + m[0][2]) / w, (m[1][0] * x + m[1][1] * y + m[1][2]) / w))
+    }
+
+    // Perspective transform taking each of four source points to the matching target point
+    // None when three of the points lie on a line
+    pub fn from_quad(source: [(f64, f64); 4], target: [(f64, f64); 4]) -> Option<Transform> {
+        // Eight equations in the eight unknown matrix entries (the last one is fixed at 1)
+        let mut system = [[0.0f64; 9]; 8];
+        for (i, (&(x, y), &(u, v))) in source.iter().zip(target.iter()).enumerate() {
+            system[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u, u];
+            system[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v, v];
+        }
+        let h = solve(system)?;
+        let transform = Transform {matrix: [[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], 1.0]]};
+        transform.inverse().map(|_| transform)
+    }
+
+    // Mirrors y within a picture of the given height, converting between rows counted
+    // from the top and rows counted from the bottom
+    fn flip_rows(height: usize) -> Transform {
+        Transform {matrix: [[1.0, 0.0, 0.0], [0.0, -1.0, height as f64 - 1.0], [0.0, 0.0, 1.0]]}
+    }
+}
+
+// Solves eight linear equations (the last column holds the right-hand side) by
+// Gaussian elimination with partial pivoting, None if they have no single solution
+fn solve(mut system: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    for column in 0..8 {
+        let pivot = (column..8).max_by(|&a, &b| system[a][column].abs().total_cmp(&system[b][column].abs()))?;
+        if system[pivot][column].abs() < 1e-12 {
+            return None;
+        }
+        system.swap(column, pivot);
+        let pivot_row = system[column];
+        for (row, values) in system.iter_mut().enumerate() {
+            if row != column {
+                let factor = values[column] / pivot_row[column];
+                for (value, pivot_value) in values.iter_mut().zip(pivot_row.iter()).skip(column) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+    }
+
+    let mut solution = [0.0; 8];
+    for (i, value) in solution.iter_mut().enumerate() {
+        *value = system[i][8] / system[i][i];
+    }
+    Some(solution)
+}
+
+// Draws the image through a transform onto a new new_width x new_height image,
+// assuming the first row of every image is the top
+// Source positions outside the image are read according to the edge mode, using the
+// background for Edge::Constant and wherever the transform can't be inverted
+pub fn warp_image<P: Pixel>(image: &Image<P>, transform: &Transform, new_width: usize, new_height: usize, interpolation: Interpolation, edge: Edge, background: P) -> Image<P> {
+    let width = image.width();
+    let height = image.height();
+    let outside = resample::premultiply(background.to_bgra());
+    let inverse = match transform.inverse() {
+        Some(inverse) => inverse,
+        None => return Image::new(new_width, new_height, background),
+    };
+
+    let fetch = |x: isize, y: isize| {
+        match (resample::edge_position(x, width, edge), resample::edge_position(y, height, edge)) {
+            (Some(x), Some(y)) => resample::premultiply(image.get(x, y).to_bgra()),
+            _ => outside,
+        }
+    };
+
+    Image::from_fn(new_width, new_height, |x, y| {
+        match inverse.apply(x as f64, y as f64) {
+            Some((source_x, source_y)) => {
+                P::from_bgra(resample::unpremultiply(resample::sample(source_x as f32, source_y as f32, interpolation, fetch)))
+            }
+            None => background,
+        }
+    })
+}
+
+// Corner pixel centers of a width x height image in the order rectify expects
+fn rectangle(width: usize, height: usize) -> [(f64, f64); 4] {
+    let (right, bottom) = (width as f64 - 1.0, height as f64 - 1.0);
+    [(0.0, 0.0), (right, 0.0), (right, bottom), (0.0, bottom)]
+}
+
+// Straightens the four-sided region with the given corners (top-left, top-right, bottom-right
+// and bottom-left, at pixel centers) into a new_width x new_height rectangle
+pub fn rectify_image<P: Pixel>(image: &Image<P>, corners: [(f64, f64); 4], new_width: usize, new_height: usize, interpolation: Interpolation, background: P) -> Image<P> {
+    match Transform::from_quad(corners, rectangle(new_width, new_height)) {
+        Some(transform) => warp_image(image, &transform, new_width, new_height, interpolation, Edge::Constant, background),
+        None => Image::new(new_width, new_height, background),
+    }
+}
+
+// Warps an image with a transform between positions as displayed, updating the header's width and height
+// TGA files normally store the bottom row first, so rows are counted from the other end for those images
+pub fn warp<P: Pixel>(image: &Data<P>, transform: &Transform, new_width: usize, new_height: usize, interpolation: Interpolation, edge: Edge, background: P) -> Data<P> {
+    let transform = if image.header.is_top_down() {
+        *transform
+    }
+    else {
+        Transform::flip_rows(image.pixels.height()).then(transform).then(&Transform::flip_rows(new_height))
+    };
+    image.with_pixels(warp_image(&image.pixels, &transform, new_width, new_height, interpolation, edge, background))
+}
+
+// Straightens a four-sided region with corners given as displayed, updating the header's width and height
+pub fn rectify<P: Pixel>(image: &Data<P>, corners: [(f64, f64); 4], new_width: usize, new_height: usize, interpolation: Interpolation, background: P) -> Data<P> {
+    // For bottom-up images the displayed bottom edge becomes the first stored row of the result
+    let corners = if image.header.is_top_down() {
+        corners
+    }
+    else {
+        let flip = |(x, y): (f64, f64)| (x, image.pixels.height() as f64 - 1.0 - y);
+        [flip(corners[3]), flip(corners[2]), flip(corners[1]), flip(corners[0])]
+    };
+    image.with_pixels(rectify_image(&image.pixels, corners, new_width, new_height, interpolation, background))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Gray;
+
+    fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+        (a.0 - b.0).abs() < 1e-6 && (a.1 - b.1).abs() < 1e-6
+    }
+
+    #[test]
+    fn test_composition_and_inverse() {
+        // Scale, then rotate a quarter turn clockwise, then move
+        let transform = Transform::scale(2.0, 3.0).then(&Transform::rotate(90.0)).then(&Transform::translate(10.0, 0.0));
+        assert!(close(transform.apply(1.0, 1.0).unwrap(), (7.0, 2.0)));
+        let inverse = transform.inverse().unwrap();
+        assert!(close(inverse.apply(7.0, 2.0).unwrap(), (1.0, 1.0)));
+        assert_eq!(Transform::scale(0.0, 1.0).inverse(), None);
+
+        assert_eq!(Transform::parse("scale=2,3").unwrap(), Transform::scale(2.0, 3.0));
+        assert_eq!(Transform::parse("matrix=1,0,5,0,1,0").unwrap(), Transform::translate(5.0, 0.0));
+        assert!(Transform::parse("rotate=1,2").is_err());
+    }
+
+    #[test]
+    fn test_quad_maps_corners() {
+        let source = [(10.0, 5.0), (90.0, 20.0), (80.0, 70.0), (5.0, 60.0)];
+        let target = [(0.0, 0.0), (49.0, 0.0), (49.0, 29.0), (0.0, 29.0)];
+        let transform = Transform::from_quad(source, target).unwrap();
+        for (from, to) in source.iter().zip(target.iter()) {
+            assert!(close(transform.apply(from.0, from.1).unwrap(), *to));
+        }
+
+        // Three corners on one line can't form a quad
+        assert_eq!(Transform::from_quad([(0.0, 0.0), (1.0, 1.0), (2.0, 2.0), (0.0, 5.0)], target), None);
+    }
+
+    #[test]
+    fn test_edge_modes() {
+        let image = Image::from_fn(3, 1, |x, _| Gray {value: (x as u8 + 1) * 10});
+        let values = |edge| {
+            let shifted = warp_image(&image, &Transform::translate(2.0, 0.0), 3, 1, Interpolation::Nearest, edge, Gray {value: 0});
+            shifted.data.iter().map(|pixel| pixel.value).collect::<Vec<_>>()
+        };
+        assert_eq!(values(Edge::Clamp), vec![10, 10, 10]);
+        assert_eq!(values(Edge::Wrap), vec![20, 30, 10]);
+        assert_eq!(values(Edge::Mirror), vec![20, 10, 10]);
+        assert_eq!(values(Edge::Constant), vec![0, 0, 10]);
+    }
+
+    #[test]
+    fn test_rectify_straightens_quad() {
+        // Bright square drawn at an angle, whose corners are known
+        let corners = [(4.0, 2.0), (13.0, 4.0), (11.0, 13.0), (2.0, 11.0)];
+        let inside = Transform::from_quad([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)], corners).unwrap().inverse().unwrap();
+        let image = Image::from_fn(16, 16, |x, y| {
+            let (u, v) = inside.apply(x as f64, y as f64).unwrap();
+            Gray {value: if (-0.05..=1.05).contains(&u) && (-0.05..=1.05).contains(&v) { 255u8 } else { 0 }}
+        });
+        let straight = rectify_image(&image, corners, 8, 8, Interpolation::Nearest, Gray {value: 0});
+        assert!(straight.data.iter().all(|pixel| pixel.value == 255));
+    }
+}